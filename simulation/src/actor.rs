@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 
-use messages::{MarketMessages, MarketHistory, ActorMessages, TransactionRequest};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor};
+use crate::messages::{MarketMessages, SharedHistory, ActorMessages, TransactionRequest};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor};
+use crate::supervisor;
+
+//Cash every actor starts the run with unless the scenario says otherwise.
+pub const STARTING_MONEY: usize = 100;
 
 pub struct Actor {
   pub id: usize,
@@ -16,19 +19,28 @@ pub struct Actor {
   pub pending_money: usize,
   pub pending_stock: (usize, usize), //Stock, quantity
   pub markets: HashMap<usize, Sender<MarketMessages>>,
-  pub history: Arc<Mutex<MarketHistory>>
+  pub history: SharedHistory
+}
+
+//A strategy that panics unwinds through here, the last chance to record what the actor held.
+impl Drop for Actor {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      supervisor::freeze_status(settled_status(self));
+    }
+  }
 }
 
-pub fn start_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
-  let mut stop_flag = false;
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_match, clippy::needless_late_init, clippy::redundant_field_names)]
+pub fn start_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
   println!("Starting Standard Actor {}", actor_id);
   let mut actor = Actor { id: actor_id,
-                          money: 100,
+                          money: starting_money,
                           stocks: HashMap::new(),
                           pending_money: 0,
                           pending_stock: (0, 0),
                           markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))};
+                          history: SharedHistory::default()};
 
   for (_, market_tx) in actor.markets.iter() {
     market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
@@ -39,10 +51,6 @@ pub fn start_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<Mark
   }
 
   loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
     //Logic
     let mark_clone = actor.markets.clone();
     let stock_clone = actor.stocks.clone();
@@ -160,17 +168,86 @@ pub fn start_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<Mark
             Time(_, _) => {},
             ReceiveActivityCount(_,_,_) => {},
             Stop(main_channel ) => {
-              main_channel.send((actor.id, "(Standard Actor) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+              main_channel.send((actor.id, "(Standard Actor) ".to_string() + status(&actor).as_str())).unwrap();
+              return;
             }
           }
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(1));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id);}
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
     }
   }
 }
 
+//Takes part in a settlement: sets money or stock aside when the market asks for it, hands it over
+//on CommitTransaction and takes it back on AbortTransaction. Any other message is given back to the strategy.
+pub fn answer_settlement(actor: &mut Actor, message: ActorMessages) -> Option<ActorMessages> {
+  match message {
+    StockRequest(stock_request) => {
+      let answer = if has_pending_transaction(actor) {
+        Cancel(actor.id)
+      }
+      else {
+        let (stock_id, quantity) = (stock_request.stock_id, stock_request.quantity);
+        match actor.stocks.get(&stock_id) {
+          Some(owned_quantity) if *owned_quantity >= quantity => {
+            remove_stock(actor, (stock_id, quantity));
+            actor.pending_stock = (stock_id, quantity);
+            Commit(actor.id)
+          },
+          _ => Cancel(actor.id)
+        }
+      };
+      if let Some(market_tx) = actor.markets.get(&stock_request.market_id) {let _ = market_tx.send(answer);}
+    },
+    MoneyRequest(money_request) => {
+      let answer = if has_pending_transaction(actor) {
+        Cancel(actor.id)
+      }
+      else if actor.money >= money_request.amount && money_request.amount > 0 {
+        actor.money -= money_request.amount;
+        actor.pending_money = money_request.amount;
+        Commit(actor.id)
+      }
+      else {
+        Cancel(actor.id)
+      };
+      if let Some(market_tx) = actor.markets.get(&money_request.market_id) {let _ = market_tx.send(answer);}
+    },
+    CommitTransaction(commit_transaction_request) => {
+      //The buyer gets the stock and whatever it set aside beyond the price.
+      if actor.pending_money > 0 {
+        let leftover_money = actor.pending_money - commit_transaction_request.price;
+        add_stock(actor, (commit_transaction_request.stock_id, commit_transaction_request.quantity));
+        actor.money += leftover_money;
+        actor.pending_money = 0;
+      }
+      //The seller gets the money and whatever it set aside beyond the quantity sold.
+      if actor.pending_stock.1 > 0 {
+        let restore_stock = (commit_transaction_request.stock_id, actor.pending_stock.1 - commit_transaction_request.quantity);
+        if restore_stock.1 > 0 {
+          add_stock(actor, restore_stock);
+        }
+        actor.money += commit_transaction_request.price;
+        actor.pending_stock = (0,0);
+      }
+    },
+    AbortTransaction => {
+      if actor.pending_stock.1 != 0 {
+        let pending_stock_clone = actor.pending_stock;
+        add_stock(actor, pending_stock_clone);
+        actor.pending_stock = (0,0);
+      }
+      if actor.pending_money > 0 {
+        actor.money += actor.pending_money;
+        actor.pending_money = 0;
+      }
+    },
+    other => {return Some(other);}
+  }
+  None
+}
+
 pub fn add_stock(actor: &mut Actor, stock_to_add: (usize, usize)) {
   let stock_clone = actor.stocks.clone();
   let held_stock = stock_clone.get(&stock_to_add.0);
@@ -186,6 +263,7 @@ pub fn add_stock(actor: &mut Actor, stock_to_add: (usize, usize)) {
   }
 }
 
+#[allow(clippy::single_match)]
 pub fn remove_stock(actor: &mut Actor, stock_to_remove: (usize, usize)) {
   let stock_clone = actor.stocks.clone();
   let held_stock = stock_clone.get(&stock_to_remove.0);
@@ -199,16 +277,29 @@ pub fn remove_stock(actor: &mut Actor, stock_to_remove: (usize, usize)) {
 }
 
 pub fn status(actor: &Actor) -> String {
-  let mut status = format!("ID: {}, Money: {}, Stocks (ID, Quantity): ", actor.id, actor.money);
-  for (id, count) in actor.stocks.iter() {
+  format_status(actor.id, actor.money, &actor.stocks)
+}
+
+//The market aborts an unsettled transaction when an actor crashes, so whatever was set aside is still the actor's.
+pub fn settled_status(actor: &Actor) -> String {
+  let mut stocks = actor.stocks.clone();
+  if actor.pending_stock.1 > 0 {
+    *stocks.entry(actor.pending_stock.0).or_insert(0) += actor.pending_stock.1;
+  }
+  format_status(actor.id, actor.money + actor.pending_money, &stocks)
+}
+
+fn format_status(id: usize, money: usize, stocks: &HashMap<usize, usize>) -> String {
+  let mut status = format!("ID: {}, Money: {}, Stocks (ID, Quantity): ", id, money);
+  for (id, count) in stocks.iter() {
     if *count == 0 {
       continue;
     }
-    status = status + "(" + id.to_string().as_slice() + ", " + count.to_string().as_slice() + ") ";
+    status = status + "(" + id.to_string().as_str() + ", " + count.to_string().as_str() + ") ";
   }
-  return status;
+  status
 }
 
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
+pub fn has_pending_transaction(actor: &Actor) -> bool {
+  actor.pending_money > 0 || actor.pending_stock.1 > 0
 }
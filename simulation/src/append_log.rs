@@ -0,0 +1,158 @@
+use std::ops::Index;
+use std::sync::Arc;
+
+/*
+An append-only list that is cheap to clone. Elements are kept in fixed size chunks, full
+chunks are shared between every clone and only the partly filled last chunk is copied.
+This keeps publishing a new MarketHistory snapshot after each trade proportional to the
+number of chunks rather than the number of trades.
+Only the last element can be changed after it was pushed, e.g. the candle still being built.
+*/
+
+const CHUNK_SIZE: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct AppendLog<T> {
+  sealed: Vec<Arc<Vec<T>>>, //Full chunks, each holding CHUNK_SIZE elements
+  tail: Vec<T>
+}
+
+impl<T> Default for AppendLog<T> {
+  fn default() -> AppendLog<T> {
+    AppendLog {sealed: vec![], tail: vec![]}
+  }
+}
+
+impl<T> AppendLog<T> {
+  pub fn new() -> AppendLog<T> {
+    AppendLog::default()
+  }
+
+  pub fn push(&mut self, element: T) {
+    //Sealing before the push keeps the last element in the tail, where last_mut can reach it.
+    if self.tail.len() == CHUNK_SIZE {
+      let full = std::mem::replace(&mut self.tail, Vec::with_capacity(CHUNK_SIZE));
+      self.sealed.push(Arc::new(full));
+    }
+    self.tail.push(element);
+  }
+
+  pub fn len(&self) -> usize {
+    self.sealed.len() * CHUNK_SIZE + self.tail.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, index: usize) -> Option<&T> {
+    let chunk = index / CHUNK_SIZE;
+    if chunk < self.sealed.len() {
+      self.sealed[chunk].get(index % CHUNK_SIZE)
+    }
+    else if chunk == self.sealed.len() {
+      self.tail.get(index % CHUNK_SIZE)
+    }
+    else {
+      None
+    }
+  }
+
+  pub fn last(&self) -> Option<&T> {
+    self.tail.last()
+  }
+
+  pub fn last_mut(&mut self) -> Option<&mut T> {
+    self.tail.last_mut()
+  }
+
+  //Index of the first element for which before is false, elements must be ordered by it.
+  pub fn partition_point<P: Fn(&T) -> bool>(&self, before: P) -> usize {
+    let (mut low, mut high) = (0, self.len());
+    while low < high {
+      let middle = low + (high - low) / 2;
+      match self.get(middle) {
+        Some(element) if before(element) => {low = middle + 1;},
+        _ => {high = middle;}
+      }
+    }
+    low
+  }
+
+  pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+    self.sealed.iter().flat_map(|chunk| chunk.iter()).chain(self.tail.iter())
+  }
+
+  pub fn view(&self) -> LogView<'_, T> {
+    self.range(0, self.len())
+  }
+
+  pub fn range(&self, start: usize, end: usize) -> LogView<'_, T> {
+    let end = end.min(self.len());
+    LogView {log: Some(self), start: start.min(end), end}
+  }
+}
+
+impl<T> Index<usize> for AppendLog<T> {
+  type Output = T;
+
+  fn index(&self, index: usize) -> &T {
+    match self.get(index) {
+      Some(element) => element,
+      None => panic!("index {} out of bounds for an AppendLog of length {}", index, self.len())
+    }
+  }
+}
+
+//Elements start..end of an AppendLog, or nothing at all.
+pub struct LogView<'a, T> {
+  log: Option<&'a AppendLog<T>>,
+  start: usize,
+  end: usize
+}
+
+impl<T> Clone for LogView<'_, T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for LogView<'_, T> {}
+
+impl<'a, T> LogView<'a, T> {
+  pub fn empty() -> LogView<'a, T> {
+    LogView {log: None, start: 0, end: 0}
+  }
+
+  pub fn len(&self) -> usize {
+    self.end - self.start
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, index: usize) -> Option<&'a T> {
+    match self.log {
+      Some(log) if index < self.len() => log.get(self.start + index),
+      _ => None
+    }
+  }
+
+  pub fn first(&self) -> Option<&'a T> {
+    self.get(0)
+  }
+
+  pub fn last(&self) -> Option<&'a T> {
+    if self.is_empty() {None} else {self.get(self.len() - 1)}
+  }
+
+  pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'a T> + 'a {
+    let view = *self;
+    (0..view.len()).filter_map(move |index| view.get(index))
+  }
+
+  pub fn to_vec(&self) -> Vec<T> where T: Clone {
+    self.iter().cloned().collect()
+  }
+}
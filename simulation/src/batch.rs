@@ -0,0 +1,147 @@
+use crate::messages::MarketHistory;
+use crate::portfolio::{value_portfolios, Valuation};
+use crate::scenario::{run_scenario, Scenario};
+use crate::stats::{mean, median, stddev};
+
+/*
+Runs one scenario many times and compares the strategies over all of them.
+Run i uses seed + i, so a batch can be repeated with the same --seed.
+
+Returns are per actor and run, in percent of the starting net worth (see portfolio.rs).
+Actors in the same run trade with each other, so the confidence intervals are taken over
+the runs: each run contributes the mean return and the share of winners of every actor type.
+*/
+
+pub struct StrategyStats {
+  pub kind: String,
+  pub samples: usize, //Actors of this type summed over all runs
+  pub mean_return: f64,
+  pub median_return: f64,
+  pub stddev_return: f64,
+  pub return_ci: (f64, f64), //95% interval of the mean return
+  pub win_rate: f64, //Percent of actors that ended with a profit
+  pub win_rate_ci: (f64, f64),
+  pub mean_drawdown: f64,
+  pub max_drawdown: f64
+}
+
+//The valuations of every run, in run order.
+pub fn run_batch(scenario: &Scenario, runs: usize, history: &MarketHistory) -> Vec<Vec<Valuation>> {
+  let mut results = vec![];
+  for run in 0..runs {
+    let seeded = Scenario {seed: scenario.seed.wrapping_add(run as u64), ..scenario.clone()};
+    let result = run_scenario(&seeded, history.clone(), None);
+    for name in result.failed.iter() {
+      println!("ERROR: {} exited with a panic in run {}", name, run + 1);
+    }
+    let valuations = value_portfolios(&result.endowments, &result.history);
+    println!("Run {}/{} (seed {}): {} trades", run + 1, runs, seeded.seed, result.history.next_seq - history.next_seq);
+    results.push(valuations);
+  }
+  results
+}
+
+//One row per actor type, best mean return first.
+pub fn leaderboard(runs: &[Vec<Valuation>]) -> Vec<StrategyStats> {
+  let mut kinds: Vec<String> = vec![];
+  for valuation in runs.iter().flatten() {
+    if !kinds.contains(&valuation.kind) {
+      kinds.push(valuation.kind.clone());
+    }
+  }
+
+  let mut board: Vec<StrategyStats> = kinds.into_iter().map(|kind| {
+    let mut returns = vec![];
+    let mut drawdowns = vec![];
+    let mut run_means = vec![];
+    let mut run_win_rates = vec![];
+    for run in runs.iter() {
+      let of_kind: Vec<&Valuation> = run.iter().filter(|valuation| valuation.kind == kind).collect();
+      if of_kind.is_empty() {
+        continue;
+      }
+      let run_returns: Vec<f64> = of_kind.iter().map(|valuation| valuation.return_pct()).collect();
+      let winners = of_kind.iter().filter(|valuation| valuation.pnl() > 0.0).count();
+      run_means.push(mean(&run_returns));
+      run_win_rates.push(100.0 * winners as f64 / of_kind.len() as f64);
+      returns.extend(run_returns);
+      drawdowns.extend(of_kind.iter().map(|valuation| valuation.max_drawdown));
+    }
+    let winners = returns.iter().filter(|r| **r > 0.0).count();
+    let (win_low, win_high) = confidence_interval(&run_win_rates);
+    StrategyStats {kind,
+                   samples: returns.len(),
+                   mean_return: mean(&returns),
+                   median_return: median(&returns),
+                   stddev_return: stddev(&returns),
+                   return_ci: confidence_interval(&run_means),
+                   win_rate: 100.0 * winners as f64 / returns.len() as f64,
+                   win_rate_ci: (win_low.max(0.0), win_high.min(100.0)),
+                   mean_drawdown: mean(&drawdowns),
+                   max_drawdown: drawdowns.iter().cloned().fold(0.0, f64::max)}
+  }).collect();
+  board.sort_by(|a, b| b.mean_return.total_cmp(&a.mean_return));
+  board
+}
+
+//95% interval of the mean using Student's t, a single value gives no spread to go on.
+pub fn confidence_interval(values: &[f64]) -> (f64, f64) {
+  let m = mean(values);
+  if values.len() < 2 {
+    return (m, m);
+  }
+  let half_width = t_critical(values.len() - 1) * stddev(values) / (values.len() as f64).sqrt();
+  (m - half_width, m + half_width)
+}
+
+//Two sided 95% critical values of Student's t by degrees of freedom.
+fn t_critical(degrees: usize) -> f64 {
+  const TABLE: [f64; 30] = [12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+                            2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+                            2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042];
+  match degrees {
+    0 => f64::INFINITY,
+    1..=30 => TABLE[degrees - 1],
+    31..=60 => 2.000,
+    61..=120 => 1.980,
+    _ => 1.960
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+  }
+
+  #[test]
+  fn confidence_interval_uses_students_t() {
+    //n = 4: mean 2.5, s = sqrt(5/3), t(3) = 3.182
+    let (low, high) = confidence_interval(&[1.0, 2.0, 3.0, 4.0]);
+    assert_close(low, 2.5 - 2.053972167938667);
+    assert_close(high, 2.5 + 2.053972167938667);
+
+    //n = 8: mean 5, s = 2.138..., t(7) = 2.365
+    let (low, high) = confidence_interval(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+    assert_close(low, 5.0 - 1.7877719573336448);
+    assert_close(high, 5.0 + 1.7877719573336448);
+  }
+
+  #[test]
+  fn confidence_interval_without_spread() {
+    assert_eq!(confidence_interval(&[]), (0.0, 0.0));
+    assert_eq!(confidence_interval(&[4.0]), (4.0, 4.0));
+    assert_eq!(confidence_interval(&[2.0, 2.0, 2.0]), (2.0, 2.0));
+  }
+
+  #[test]
+  fn critical_values_by_degrees_of_freedom() {
+    assert_eq!(t_critical(0), f64::INFINITY);
+    assert_close(t_critical(1), 12.706);
+    assert_close(t_critical(30), 2.042);
+    assert_close(t_critical(45), 2.000);
+    assert_close(t_critical(1000), 1.960);
+  }
+}
@@ -0,0 +1,290 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::messages::{ActorMessages, MarketHistory, MarketMessages, SharedHistory, Side, TellerMessages, TransactionRequest};
+use crate::market;
+use crate::teller;
+
+/*
+Benchmarks run from the command line instead of a simulation, see the README.
+Each prints a table; latencies are wall clock times in microseconds.
+*/
+
+const STOCKS: usize = 5;
+const SEED_TRADES: usize = 10_000;
+const RUN_TIME: Duration = Duration::from_secs(2);
+
+//Compares the old Arc<Mutex<MarketHistory>> with SharedHistory snapshots. actors reader threads
+//query the history the way the actors do while one writer records trades as fast as it can.
+pub fn bench_history(actors: usize) {
+  println!("History sharing with {} readers, {} seeded trades, {:?} per run", actors, SEED_TRADES, RUN_TIME);
+  println!("{:<10} {:>12} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+    "sharing", "reads/s", "commits/s", "read p50", "read p99", "commit p50", "commit p99", "commit max");
+
+  let locked = Arc::new(Mutex::new(seeded_history()));
+  let reader_history = locked.clone();
+  let (reads, commits) = run_history_bench(actors,
+    move || {
+      let h = reader_history.lock().unwrap();
+      query(&h)
+    },
+    move |i| {
+      let mut h = locked.lock().unwrap();
+      record(&mut h, i);
+    });
+  print_history_row("mutex", reads, commits);
+
+  let shared = SharedHistory::new(seeded_history());
+  let reader_history = shared.clone();
+  let (reads, commits) = run_history_bench(actors,
+    move || query(&reader_history.snapshot()),
+    move |i| {shared.update(|h| record(h, i));});
+  print_history_row("snapshot", reads, commits);
+}
+
+fn seeded_history() -> MarketHistory {
+  let mut history = MarketHistory {stocks: (0..STOCKS).collect(), ..MarketHistory::default()};
+  for i in 0..SEED_TRADES {
+    record(&mut history, i);
+  }
+  history
+}
+
+fn record(history: &mut MarketHistory, i: usize) {
+  let stock_id = i % STOCKS;
+  let price = 20 + i % 7;
+  let buyer = TransactionRequest {transaction_id: i, actor_id: 0, stock_id, price, quantity: 1};
+  let seller = TransactionRequest {transaction_id: i, actor_id: 1, stock_id, price, quantity: 1};
+  let time = history.next_seq;
+  history.record_trade(0, time, (buyer, seller), Side::Buy, 0, 0);
+}
+
+//What a strategy typically asks for each stock on every pass through its loop.
+fn query(history: &MarketHistory) -> usize {
+  let mut seen = 0;
+  for stock_id in history.stocks.iter() {
+    seen += history.last_sold_price(*stock_id).unwrap_or(0);
+    if history.increasing(*stock_id) {
+      seen += 1;
+    }
+    seen += history.recent_trades(*stock_id, history.next_seq, 100).len();
+  }
+  seen
+}
+
+//Returns the sorted read and commit latencies in nanoseconds.
+fn run_history_bench<R, W>(actors: usize, read: R, mut write: W) -> (Vec<u64>, Vec<u64>)
+  where R: Fn() -> usize + Send + Sync + 'static, W: FnMut(usize) + Send + 'static {
+  let read = Arc::new(read);
+  let stop = Arc::new(AtomicBool::new(false));
+  let start = Arc::new(Barrier::new(actors + 2));
+
+  let mut readers = vec![];
+  for _ in 0..actors {
+    let (read, stop, start) = (read.clone(), stop.clone(), start.clone());
+    readers.push(thread::spawn(move || {
+      let mut latencies = vec![];
+      start.wait();
+      while !stop.load(Ordering::Relaxed) {
+        let began = Instant::now();
+        std::hint::black_box(read());
+        latencies.push(began.elapsed().as_nanos() as u64);
+        //Actors sleep between passes as well.
+        thread::sleep(Duration::from_millis(10));
+      }
+      latencies
+    }));
+  }
+  let writer_stop = stop.clone();
+  let writer_start = start.clone();
+  let writer = thread::spawn(move || {
+    let mut latencies = vec![];
+    writer_start.wait();
+    let mut i = SEED_TRADES;
+    while !writer_stop.load(Ordering::Relaxed) {
+      let began = Instant::now();
+      write(i);
+      latencies.push(began.elapsed().as_nanos() as u64);
+      i += 1;
+    }
+    latencies
+  });
+
+  start.wait();
+  thread::sleep(RUN_TIME);
+  stop.store(true, Ordering::SeqCst);
+  let mut reads: Vec<u64> = readers.into_iter().flat_map(|reader| reader.join().unwrap()).collect();
+  let mut commits = writer.join().unwrap();
+  reads.sort_unstable();
+  commits.sort_unstable();
+  (reads, commits)
+}
+
+fn print_history_row(name: &str, reads: Vec<u64>, commits: Vec<u64>) {
+  let seconds = RUN_TIME.as_secs_f64();
+  println!("{:<10} {:>12.0} {:>12.0} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}", name,
+    reads.len() as f64 / seconds, commits.len() as f64 / seconds,
+    micros(percentile(&reads, 50.0)), micros(percentile(&reads, 99.0)),
+    micros(percentile(&commits, 50.0)), micros(percentile(&commits, 99.0)), micros(commits.last().cloned().unwrap_or(0)));
+}
+
+//Nearest rank percentile of already sorted samples.
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+  if sorted.is_empty() {
+    return 0;
+  }
+  let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+  sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn micros(nanos: u64) -> f64 {
+  nanos as f64 / 1000.0
+}
+
+const TELLER_BOOK_SIZES: [usize; 4] = [100, 1_000, 10_000, 100_000];
+const PENDING_SIZES: [usize; 4] = [0, 100, 1_000, 10_000];
+const ORDERS_PER_SIZE: usize = 1_000;
+
+struct MatchingRow {
+  stage: &'static str,
+  book_size: usize,
+  per_second: f64,
+  latencies: Vec<u64>
+}
+
+//Drives synthetic order flow through a teller and through a whole market at increasing book sizes.
+pub fn bench_matching() {
+  let mut rows = vec![];
+  for book_size in TELLER_BOOK_SIZES.iter() {
+    rows.push(bench_teller(*book_size));
+  }
+  for pending in PENDING_SIZES.iter() {
+    rows.push(bench_settlement(*pending));
+  }
+
+  //The market prints every commit, the results come after all of that.
+  println!("\nMatching and settlement, {} orders per book size", ORDERS_PER_SIZE);
+  println!("{:<12} {:>10} {:>12} {:>10} {:>10} {:>10} {:>10}", "stage", "book size", "per second", "p50", "p90", "p99", "max");
+  for row in rows.iter() {
+    println!("{:<12} {:>10} {:>12.0} {:>10.1} {:>10.1} {:>10.1} {:>10.1}", row.stage, row.book_size, row.per_second,
+      micros(percentile(&row.latencies, 50.0)), micros(percentile(&row.latencies, 90.0)),
+      micros(percentile(&row.latencies, 99.0)), micros(row.latencies.last().cloned().unwrap_or(0)));
+  }
+}
+
+fn order(transaction_id: usize, actor_id: usize, price: usize) -> TransactionRequest {
+  TransactionRequest {transaction_id, actor_id, stock_id: 0, price, quantity: 1}
+}
+
+//book_size resting buys that never match, then a sell that has to pass all of them to reach
+//the one buy it matches. Latency is from sending the sell until the market hears of the match.
+fn bench_teller(book_size: usize) -> MatchingRow {
+  let (market_tx, market_rx): (Sender<MarketMessages>, Receiver<MarketMessages>) = channel();
+  let (teller_tx, teller_rx): (Sender<TellerMessages>, Receiver<TellerMessages>) = channel();
+  let shutdown = Arc::new(AtomicBool::new(false));
+  let teller_shutdown = shutdown.clone();
+  let handle = thread::spawn(move || {teller::start_teller(0, market_tx, teller_rx, teller_shutdown);});
+
+  for i in 0..book_size {
+    teller_tx.send(TellerMessages::BuyRequest(order(i, i, 1))).unwrap();
+  }
+  let mut latencies = vec![];
+  let began = Instant::now();
+  for i in 0..ORDERS_PER_SIZE {
+    teller_tx.send(TellerMessages::BuyRequest(order(book_size + i, book_size + i, 100))).unwrap();
+    let sent = Instant::now();
+    teller_tx.send(TellerMessages::SellRequest(order(i, usize::MAX, 50))).unwrap();
+    match market_rx.recv() {
+      Ok(MarketMessages::MatchRequest(_, _, _)) => {latencies.push(sent.elapsed().as_nanos() as u64);},
+      _ => {break;}
+    }
+  }
+  let elapsed = began.elapsed().as_secs_f64();
+
+  drop(teller_tx);
+  handle.join().unwrap();
+  latencies.sort_unstable();
+  MatchingRow {stage: "teller", book_size, per_second: latencies.len() as f64 / elapsed, latencies}
+}
+
+//A whole market with pending matches that can't settle: actor 0 never answers, so everything
+//it matched with waits behind its active transaction. Pairs of actors that commit straight away
+//then trade through it one at a time. Latency is from sending the buy until the buyer is told
+//the trade settled.
+fn bench_settlement(pending: usize) -> MatchingRow {
+  const PAIRS: usize = 10;
+  let (market_tx, market_rx): (Sender<MarketMessages>, Receiver<MarketMessages>) = channel();
+  let market_tx_clone = market_tx.clone();
+  let handle = thread::spawn(move || {
+    market::start_market(0, market_tx_clone, market_rx, 1, 0, SharedHistory::default(), None);});
+
+  //Actor 0 keeps its requests unanswered, actor 1 sells to it.
+  let (stalled_tx, stalled_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+  market_tx.send(MarketMessages::RegisterActor(0, stalled_tx)).unwrap();
+  let mut proxies = vec![];
+  let (settled_tx, settled_rx): (Sender<usize>, Receiver<usize>) = channel();
+  for actor_id in 1..2 + 2 * PAIRS {
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    market_tx.send(MarketMessages::RegisterActor(actor_id, actor_tx.clone())).unwrap();
+    let proxy_market_tx = market_tx.clone();
+    let proxy_settled_tx = settled_tx.clone();
+    proxies.push((actor_tx, thread::spawn(move || {
+      while let Ok(message) = actor_rx.recv() {
+        match message {
+          ActorMessages::MoneyRequest(_) | ActorMessages::StockRequest(_) => {let _ = proxy_market_tx.send(MarketMessages::Commit(actor_id));},
+          ActorMessages::CommitTransaction(_) => {let _ = proxy_settled_tx.send(actor_id);},
+          ActorMessages::Stop(_) => {return;},
+          _ => {}
+        }
+      }
+    })));
+  }
+
+  for i in 0..pending + 1 {
+    market_tx.send(MarketMessages::BuyRequest(order(i, 0, 10))).unwrap();
+    market_tx.send(MarketMessages::SellRequest(order(i, 1, 10))).unwrap();
+  }
+  //Wait until the teller has matched all of them, the market handles the matches before anything sent after.
+  let (barrier_tx, barrier_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+  market_tx.send(MarketMessages::RegisterActor(usize::MAX, barrier_tx)).unwrap();
+  market_tx.send(MarketMessages::RequestActivityCount(usize::MAX, 0, true)).unwrap();
+  let _ = barrier_rx.recv();
+
+  let mut latencies = vec![];
+  let began = Instant::now();
+  for i in 0..ORDERS_PER_SIZE {
+    let buyer = 2 + 2 * (i % PAIRS);
+    let sent = Instant::now();
+    market_tx.send(MarketMessages::BuyRequest(order(i, buyer, 10))).unwrap();
+    market_tx.send(MarketMessages::SellRequest(order(i, buyer + 1, 10))).unwrap();
+    //Settled once both sides are told.
+    let mut told = 0;
+    while told < 2 {
+      match settled_rx.recv() {
+        Ok(_) => {told += 1;},
+        Err(_) => {break;}
+      }
+    }
+    latencies.push(sent.elapsed().as_nanos() as u64);
+  }
+  let elapsed = began.elapsed().as_secs_f64();
+
+  let (closed_tx, closed_rx): (Sender<usize>, Receiver<usize>) = channel();
+  market_tx.send(MarketMessages::Shutdown(closed_tx)).unwrap();
+  let _ = closed_rx.recv();
+  drop(market_tx);
+  drop(stalled_rx);
+  drop(barrier_rx);
+  drop(settled_tx);
+  let (stop_tx, _stop_rx) = channel::<(usize, String)>();
+  for (actor_tx, proxy) in proxies {
+    let _ = actor_tx.send(ActorMessages::Stop(stop_tx.clone()));
+    proxy.join().unwrap();
+  }
+  handle.join().unwrap();
+  latencies.sort_unstable();
+  MatchingRow {stage: "settlement", book_size: pending, per_second: latencies.len() as f64 / elapsed, latencies}
+}
@@ -1,31 +1,30 @@
 use std::collections::HashMap;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 
-use messages::{ActorMessages, TransactionRequest, MarketMessages, MarketHistory};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{SellRequest, Commit, Cancel, RegisterActor};
-use actor::Actor;
-use actor::{add_stock, remove_stock, status};
+use crate::messages::{ActorMessages, TransactionRequest, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{SellRequest, Commit, Cancel, RegisterActor};
+use crate::actor::Actor;
+use crate::actor::{add_stock, has_pending_transaction, remove_stock, status};
 
 /*
 This is a corporate actor. Their only desire is to sell stocks. They do not adjust
 their prices and instead only want to get their stock out into the market.
 */
 
-pub fn start_corporate_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, stock_id: usize, starting_quantity: usize, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_match, clippy::needless_late_init, clippy::redundant_field_names)]
+pub fn start_corporate_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, stock_id: usize, starting_quantity: usize, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
   println!("Starting Corporate Actor {}", actor_id);
-  let mut stop_flag = false;
   let mut actor = Actor { id: actor_id,
-                          money: 100,
+                          money: starting_money,
                           stocks: HashMap::new(),
                           pending_money: 0,
                           pending_stock: (0, 0),
                           markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))};
+                          history: SharedHistory::default()};
   actor.stocks.insert(stock_id, starting_quantity);
   let next_transaction_id = 0;
 
@@ -35,11 +34,6 @@ pub fn start_corporate_actor(actor_id: usize, existing_markets: HashMap<usize, S
   }
 
   loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
-
     //Logic
     if actor.pending_stock.1 == 0 {
       for (stock_id, quantity) in actor.stocks.iter() {
@@ -169,17 +163,13 @@ pub fn start_corporate_actor(actor_id: usize, existing_markets: HashMap<usize, S
             Time(_, _) => {},
             ReceiveActivityCount(_,_,_) => {},
             Stop(main_channel) => {
-              main_channel.send((actor.id, "(Corporate Actor) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+              main_channel.send((actor.id, "(Corporate Actor) ".to_string() + status(&actor).as_str())).unwrap();
+              return;
             }
           }
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(1));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id);}
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
     }
   }
 }
-
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
-}
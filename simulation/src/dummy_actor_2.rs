@@ -1,25 +1,25 @@
 use std::collections::HashMap;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 
-use messages::{MarketMessages, MarketHistory, ActorMessages, TransactionRequest};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
-use actor::Actor;
-use actor::{add_stock, remove_stock, status};
+use crate::messages::{MarketMessages, SharedHistory, ActorMessages, TransactionRequest};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
+use crate::actor::Actor;
+use crate::actor::{add_stock, has_pending_transaction, remove_stock, status};
 
-pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_if, clippy::collapsible_match, clippy::needless_late_init, clippy::redundant_field_names, clippy::single_match)]
+pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
   println!("Starting Dummy_Actor_2 {}", actor_id);
   let mut actor = Actor { id: actor_id,
-                          money: 100,
+                          money: starting_money,
                           stocks: HashMap::new(),
                           pending_money: 0,
                           pending_stock: (0, 0),
                           markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))};
+                          history: SharedHistory::default()};
 
   //Register the actor with every market
   for (_, market_tx) in actor.markets.iter() {
@@ -31,7 +31,6 @@ pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sen
 
   //Number of stocks available to buy
   let mut init_history = false;
-  let mut stop_flag = false;
   let mut to_sell_prices = HashMap::new();
   let mut stock_id_incr = 0;
   let mut active_buy_requests = HashMap::new();
@@ -39,11 +38,6 @@ pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sen
   let mut backup_sell_requests = HashMap::new();
 
   loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
-
     //Logic
     let mark_clone = actor.markets.clone();
     let stock_clone = actor.stocks.clone();
@@ -55,7 +49,7 @@ pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sen
       //Get variables for the actor's stocks
       let local_stocks;
       {
-        local_stocks = actor.history.lock().unwrap().stocks.clone();
+        local_stocks = actor.history.snapshot().stocks.clone();
       }
 
       //Iterate through the actor's stocks
@@ -104,7 +98,7 @@ pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sen
             if current_time < 3 * (max_time / 4) {
               if !to_sell_prices.contains_key(stock) {
                 //Make the price he should buy it at the most recently bought price
-                let buy_price = actor.history.lock().unwrap().last_sold_price(*stock);
+                let buy_price = actor.history.snapshot().last_sold_price(*stock);
                 match buy_price {
                   //If the stock was last bought at a price
                   Some(price) => {
@@ -255,17 +249,13 @@ pub fn start_dummy_actor_2(actor_id: usize, existing_markets: HashMap<usize, Sen
             },
             ReceiveActivityCount(_,_,_) => {},
             Stop(main_channel) => {
-              main_channel.send((actor.id, "(Dumb Actor 2) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+              main_channel.send((actor.id, "(Dumb Actor 2) ".to_string() + status(&actor).as_str())).unwrap();
+              return;
             }
           }
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(1));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id);}
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
     }
   }
 }
-
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
-}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::messages::{ActorMessages, Candle, MarketHistory, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{RegisterActor, RequestActivityCount};
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, settled_status};
+use crate::portfolio::mark_price;
+use crate::scenario::{start_session, RunResult, Scenario, Session};
+use crate::trading::RestingOrders;
+
+/*
+The market as a step based environment for learning agents, run in the same process as the
+scenario's population. reset starts a new run of the scenario with the given seed and adds an
+Agent actor to it, whose orders the learner decides. Every step sends the learner's actions,
+moves the clock ticks_per_step ticks and returns what the agent sees afterwards:
+
+  observation  the agent's cash and holdings, and for every stock the last unit price, the last
+               few candles and the depth of the book, the number of requests resting on each side
+  reward       the change in the agent's net worth, holdings marked to the last unit price as in
+               portfolio.rs
+  done         once the scenario's last tick has passed
+
+Actions are the orders a strategy can make through trading::RestingOrders, at prices per share,
+so each stock has one order resting at a time. Settlement is answered for the agent as for any
+other actor. The clock waits for the learner between steps, actors that trade on Time messages
+wait with it. close, or the next reset, ends the run and returns its result for the usual reports.
+*/
+
+#[derive(Clone)]
+pub struct EnvironmentConfig {
+  pub candles: usize, //Most recent candles of each stock in an observation
+  pub ticks_per_step: usize
+}
+
+impl Default for EnvironmentConfig {
+  fn default() -> EnvironmentConfig {
+    EnvironmentConfig {candles: 10, ticks_per_step: 1}
+  }
+}
+
+pub enum Action {
+  Buy {stock_id: usize, price: usize, shares: usize},
+  Sell {stock_id: usize, price: usize, shares: usize},
+  Cancel(usize), //stock_id
+  CancelAll
+}
+
+pub struct StockObservation {
+  pub stock_id: usize,
+  pub last_price: Option<f64>, //Of one share
+  pub candles: Vec<Candle>, //Oldest first
+  pub bids: usize, //Buy requests resting with the stock's teller
+  pub asks: usize //Sell requests resting with the stock's teller
+}
+
+pub struct Observation {
+  pub time: usize,
+  pub cash: usize,
+  pub holdings: HashMap<usize, usize>, //stock_id, quantity
+  pub stocks: Vec<StockObservation>
+}
+
+pub struct Step {
+  pub observation: Observation,
+  pub reward: f64,
+  pub done: bool
+}
+
+//What the agent holds, money and stock set aside for a settlement included, and the depth it last heard of.
+struct Account {
+  cash: usize,
+  holdings: HashMap<usize, usize>,
+  depth: HashMap<usize, (usize, usize)> //stock_id, (bids, asks)
+}
+
+enum Control {
+  Act(Vec<Action>),
+  Observe(Sender<Account>)
+}
+
+struct Episode {
+  session: Session,
+  control_tx: Sender<Control>,
+  ticks_done: usize,
+  net_worth: f64
+}
+
+pub struct Environment {
+  scenario: Scenario,
+  history: MarketHistory, //What every episode starts from
+  config: EnvironmentConfig,
+  episode: Option<Episode>
+}
+
+impl Environment {
+  pub fn new(scenario: Scenario, history: MarketHistory, config: EnvironmentConfig) -> Environment {
+    Environment {scenario, history, config, episode: None}
+  }
+
+  //Ends any episode under way and starts a new one, seed decides the population's random choices.
+  pub fn reset(&mut self, seed: u64) -> Observation {
+    self.close();
+    self.scenario.seed = seed;
+    let mut session = start_session(&self.scenario, self.history.clone(), None);
+    let (control_tx, control_rx): (Sender<Control>, Receiver<Control>) = channel();
+    let money = self.scenario.starting_money;
+    session.spawn("Agent", money, move |id, markets, actor_tx, actor_rx| {start_agent(id, markets, money, control_rx, actor_tx, actor_rx);});
+    let mut episode = Episode {session, control_tx, ticks_done: 0, net_worth: 0.0};
+    let (observation, net_worth) = observe(&episode, &self.config, 0);
+    episode.net_worth = net_worth;
+    self.episode = Some(episode);
+    observation
+  }
+
+  pub fn step(&mut self, actions: Vec<Action>) -> Step {
+    let (tick, ticks) = (self.scenario.tick, self.scenario.ticks);
+    let episode = match self.episode {
+      Some(ref mut episode) => episode,
+      None => panic!("Environment::step called before reset")
+    };
+    let _ = episode.control_tx.send(Control::Act(actions));
+    for _ in 0..self.config.ticks_per_step.max(1) {
+      if episode.ticks_done < ticks {
+        episode.session.tick(episode.ticks_done);
+        episode.ticks_done += 1;
+      }
+    }
+    let (observation, net_worth) = observe(episode, &self.config, episode.ticks_done.saturating_sub(1) * tick);
+    let reward = net_worth - episode.net_worth;
+    episode.net_worth = net_worth;
+    Step {observation, reward, done: episode.ticks_done >= ticks}
+  }
+
+  //The agent's net worth as of the last observation.
+  pub fn net_worth(&self) -> Option<f64> {
+    self.episode.as_ref().map(|episode| episode.net_worth)
+  }
+
+  //Ends the episode under way, None without one.
+  pub fn close(&mut self) -> Option<RunResult> {
+    self.episode.take().map(|episode| episode.session.finish())
+  }
+}
+
+fn observe(episode: &Episode, config: &EnvironmentConfig, time: usize) -> (Observation, f64) {
+  let (reply_tx, reply_rx): (Sender<Account>, Receiver<Account>) = channel();
+  let account = match episode.control_tx.send(Control::Observe(reply_tx)) {
+    Ok(_) => reply_rx.recv().ok(),
+    Err(_) => None
+  };
+  //An agent that crashed holds nothing any more, see supervisor.rs.
+  let account = account.unwrap_or(Account {cash: 0, holdings: HashMap::new(), depth: HashMap::new()});
+  let history = episode.session.history();
+  let stocks = history.stocks.iter().map(|stock_id| {
+    let mut candles: Vec<Candle> = history.candles(*stock_id).iter().rev().take(config.candles).cloned().collect();
+    candles.reverse();
+    let (bids, asks) = *account.depth.get(stock_id).unwrap_or(&(0, 0));
+    StockObservation {stock_id: *stock_id, last_price: history.last_unit_price(*stock_id), candles, bids, asks}
+  }).collect();
+  let net_worth = account.cash as f64 + account.holdings.iter().map(|(stock_id, held)| *held as f64 * mark_price(*stock_id, &history)).sum::<f64>();
+  (Observation {time, cash: account.cash, holdings: account.holdings, stocks}, net_worth)
+}
+
+fn start_agent(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, control_rx: Receiver<Control>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Agent {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut orders = RestingOrders::default();
+  let mut depth: HashMap<usize, (usize, usize)> = HashMap::new();
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    let mut idle = true;
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        idle = false;
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          //The book's depth as of every tick, the tellers answer with ReceiveActivityCount.
+          Some(Time(_, _)) => {
+            for stock_id in actor.history.snapshot().stocks.iter() {
+              for (_, market_tx) in actor.markets.iter() {
+                let _ = market_tx.send(RequestActivityCount(actor.id, *stock_id, true));
+                let _ = market_tx.send(RequestActivityCount(actor.id, *stock_id, false));
+              }
+            }
+          },
+          Some(ReceiveActivityCount(stock_id, buying, count)) => {
+            let side = depth.entry(stock_id).or_insert((0, 0));
+            if buying {side.0 = count;} else {side.1 = count;}
+          },
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Agent) ".to_string() + settled_status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Agent {} disconnected", actor.id); return;}
+    }
+    match control_rx.try_recv() {
+      Ok(Control::Act(actions)) => {
+        idle = false;
+        for action in actions {
+          match action {
+            Action::Buy {stock_id, price, shares} => {orders.buy(&actor, stock_id, price, shares);},
+            Action::Sell {stock_id, price, shares} => {orders.sell(&actor, stock_id, price, shares);},
+            Action::Cancel(stock_id) => {orders.revoke(&actor, stock_id);},
+            Action::CancelAll => {orders.revoke_all(&actor);}
+          }
+        }
+      },
+      Ok(Control::Observe(reply_tx)) => {
+        idle = false;
+        let mut holdings = actor.stocks.clone();
+        if actor.pending_stock.1 > 0 {
+          *holdings.entry(actor.pending_stock.0).or_insert(0) += actor.pending_stock.1;
+        }
+        holdings.retain(|_, held| *held > 0);
+        let _ = reply_tx.send(Account {cash: actor.money + actor.pending_money, holdings, depth: depth.clone()});
+      },
+      //The environment is gone or has nothing to say, the agent keeps settling until it is stopped.
+      Err(_) => {}
+    }
+    if idle {
+      thread::sleep(Duration::from_millis(1));
+    }
+  }
+}
+
+//A baseline policy: now and then an order for a stock at up to 10% off its last price.
+pub fn random_actions(observation: &Observation, rng: &mut StdRng) -> Vec<Action> {
+  let mut actions = vec![];
+  for stock in observation.stocks.iter() {
+    if rng.gen::<f64>() >= 0.1 {
+      continue;
+    }
+    let last = stock.last_price.unwrap_or(50.0);
+    let price = ((last * rng.gen_range(0.9..1.1)).round() as usize).max(1);
+    let held = *observation.holdings.get(&stock.stock_id).unwrap_or(&0);
+    match rng.gen_range(0..3) {
+      0 if observation.cash >= price => {actions.push(Action::Buy {stock_id: stock.stock_id, price, shares: rng.gen_range(1..=(observation.cash / price).min(5))});},
+      1 if held > 0 => {actions.push(Action::Sell {stock_id: stock.stock_id, price, shares: rng.gen_range(1..=held)});},
+      2 => {actions.push(Action::Cancel(stock.stock_id));},
+      _ => {}
+    }
+  }
+  actions
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small_scenario() -> Scenario {
+    let mut scenario = Scenario::default();
+    scenario.only_zi();
+    scenario.corporate_actors = 1;
+    scenario.tick = 5;
+    scenario.ticks = 4;
+    scenario
+  }
+
+  #[test]
+  fn rewards_add_up_to_the_change_in_net_worth_until_done() {
+    let scenario = small_scenario();
+    let mut environment = Environment::new(scenario.clone(), MarketHistory::default(), EnvironmentConfig {candles: 2, ticks_per_step: 2});
+    let observation = environment.reset(7);
+    assert_eq!(observation.cash, scenario.starting_money);
+    assert_eq!(environment.net_worth(), Some(scenario.starting_money as f64));
+
+    let mut rewards = 0.0;
+    let mut steps = vec![];
+    loop {
+      let before = environment.net_worth().unwrap();
+      let step = environment.step(vec![Action::Buy {stock_id: 0, price: 60, shares: 1}]);
+      assert!((step.reward - (environment.net_worth().unwrap() - before)).abs() < 1e-9);
+      rewards += step.reward;
+      steps.push((step.observation.time, step.done));
+      if step.done {
+        break;
+      }
+    }
+    assert_eq!(steps, vec![(5, false), (15, true)]);
+    assert!((rewards - (environment.net_worth().unwrap() - scenario.starting_money as f64)).abs() < 1e-9);
+    assert!(environment.close().is_some());
+  }
+
+  #[test]
+  #[should_panic(expected = "step called before reset")]
+  fn step_before_reset_panics() {
+    let mut environment = Environment::new(small_scenario(), MarketHistory::default(), EnvironmentConfig::default());
+    environment.step(vec![]);
+  }
+}
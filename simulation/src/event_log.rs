@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::TransactionRequest;
+
+/*
+A JSON lines record of everything a market does. The market thread is the only writer,
+so entries are appended in the order the market handled them and seq is strictly increasing.
+*/
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event")]
+pub enum Event {
+  Open {stock_count: usize, #[serde(default)] fee_bps: usize}, //One teller per stock id below stock_count
+  Close,
+  Tick {max_time: usize},
+  BuyOrder {request: TransactionRequest},
+  SellOrder {request: TransactionRequest},
+  Revoke {stock_id: usize, actor_id: usize, transaction_id: usize},
+  Match {buyer: TransactionRequest, seller: TransactionRequest}, //As reported by the teller
+  MoneyRequest {actor_id: usize, amount: usize},
+  StockRequest {actor_id: usize, stock_id: usize, quantity: usize},
+  Commit {actor_id: usize},
+  Cancel {actor_id: usize},
+  Settled {buyer: TransactionRequest, seller: TransactionRequest},
+  Aborted {buyer: TransactionRequest, seller: TransactionRequest},
+  Crashed {actor_id: usize}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogEntry {
+  pub seq: usize,
+  pub time: usize, //Simulated time of the last tick the market saw
+  pub market_id: usize,
+  #[serde(flatten)]
+  pub event: Event
+}
+
+pub struct EventLog {
+  writer: BufWriter<File>,
+  next_seq: usize,
+  failed: bool
+}
+
+impl EventLog {
+  //Starts a fresh log, replacing anything already at path.
+  pub fn create(path: &str) -> io::Result<EventLog> {
+    let file = File::create(path)?;
+    Ok(EventLog {writer: BufWriter::new(file), next_seq: 0, failed: false})
+  }
+
+  pub fn record(&mut self, time: usize, market_id: usize, event: Event) {
+    let entry = LogEntry {seq: self.next_seq, time, market_id, event};
+    self.next_seq += 1;
+    if self.failed {
+      return;
+    }
+    let written = serde_json::to_writer(&mut self.writer, &entry).map_err(io::Error::from)
+      .and_then(|_| self.writer.write_all(b"\n"));
+    if let Err(e) = written {
+      //Keep the simulation running, but say once that the log is incomplete.
+      println!("ERROR: event log stopped at entry {}: {}", entry.seq, e);
+      self.failed = true;
+    }
+  }
+
+  pub fn flush(&mut self) {
+    if let Err(e) = self.writer.flush() {
+      println!("ERROR: could not flush event log: {}", e);
+    }
+  }
+}
+
+pub fn read_event_log(path: &str) -> io::Result<Vec<LogEntry>> {
+  let reader = BufReader::new(File::open(path)?);
+  let mut entries = vec![];
+  for line in reader.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    entries.push(serde_json::from_str(&line).map_err(io::Error::from)?);
+  }
+  Ok(entries)
+}
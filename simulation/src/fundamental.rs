@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::stats::mean;
+use crate::messages::MarketHistory;
+use crate::portfolio::run_trades;
+
+/*
+A hidden fundamental value for every listed stock, generated before the run and kept in
+MarketHistory::fundamentals, one value per step. Only informed actors look at it.
+
+Both processes move the logarithm of the value. A random walk takes a normally distributed
+step with the given drift and volatility, a jump diffusion also jumps with jump_probability
+per step by a normally distributed jump_size. Values are whole units, at least 1.
+
+Tracking compares every trade's unit price with the fundamental value at the time it settled.
+*/
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FundamentalProcess {
+  RandomWalk,
+  JumpDiffusion
+}
+
+impl FundamentalProcess {
+  pub fn parse(name: &str) -> Option<FundamentalProcess> {
+    match name {
+      "random-walk" => Some(FundamentalProcess::RandomWalk),
+      "jump-diffusion" => Some(FundamentalProcess::JumpDiffusion),
+      _ => None
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct FundamentalConfig {
+  pub process: FundamentalProcess,
+  pub start: f64, //Every stock's value at time 0
+  pub drift: f64, //Mean log change per step
+  pub volatility: f64, //Standard deviation of the log change per step
+  pub jump_probability: f64, //Chance of a jump in a step
+  pub jump_size: f64, //Standard deviation of the log change of a jump
+  pub step: usize //Milliseconds each value holds for
+}
+
+impl Default for FundamentalConfig {
+  fn default() -> FundamentalConfig {
+    FundamentalConfig {process: FundamentalProcess::RandomWalk, start: 50.0, drift: 0.0, volatility: 0.01,
+                       jump_probability: 0.01, jump_size: 0.1, step: 100}
+  }
+}
+
+//Fills in the fundamental values of stocks 0..stock_count for a run lasting duration milliseconds.
+pub fn generate(history: &mut MarketHistory, config: &FundamentalConfig, stock_count: usize, duration: usize, seed: u64) {
+  let mut rng = StdRng::seed_from_u64(!seed);
+  let steps = duration / config.step.max(1) + 1;
+  history.fundamental_step = config.step.max(1);
+  for stock_id in 0..stock_count {
+    let mut log_value = config.start.max(1.0).ln();
+    let mut values = Vec::with_capacity(steps);
+    for _ in 0..steps {
+      values.push((log_value.exp().round() as usize).max(1));
+      log_value += config.drift + config.volatility * normal(&mut rng);
+      if config.process == FundamentalProcess::JumpDiffusion && rng.gen::<f64>() < config.jump_probability {
+        log_value += config.jump_size * normal(&mut rng);
+      }
+    }
+    history.fundamentals.insert(stock_id, Arc::new(values));
+  }
+}
+
+//A standard normal draw (Box-Muller).
+pub fn normal(rng: &mut StdRng) -> f64 {
+  let u: f64 = 1.0 - rng.gen::<f64>();
+  let v: f64 = rng.gen();
+  (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+//How closely one stock's trades followed its fundamental value.
+pub struct Tracking {
+  pub stock_id: usize,
+  pub trades: usize,
+  pub mean_abs_error: f64, //Mean of |price - value| / value, in percent
+  pub rms_error: f64, //Root mean square of the same, in percent
+  pub within_10: f64, //Percent of trades within 10% of the value
+  pub correlation: Option<f64>,
+  pub final_price: Option<f64>,
+  pub final_value: Option<usize>
+}
+
+pub fn tracking(history: &MarketHistory) -> Vec<Tracking> {
+  let trades = run_trades(history);
+  let mut stock_ids: Vec<usize> = history.fundamentals.keys().cloned().collect();
+  stock_ids.sort();
+  stock_ids.into_iter().map(|stock_id| {
+    //Trades are stamped after a loaded history, the fundamental values start with the run.
+    let pairs: Vec<(f64, f64)> = trades.iter()
+      .filter(|trade| trade.buyer.stock_id == stock_id)
+      .filter_map(|trade| match (trade.unit_price(), history.fundamental_value(stock_id, trade.time - history.time_offset)) {
+        (Some(price), Some(value)) => Some((price, value as f64)),
+        _ => None
+      }).collect();
+    let errors: Vec<f64> = pairs.iter().map(|(price, value)| 100.0 * (price - value).abs() / value).collect();
+    Tracking {stock_id,
+              trades: pairs.len(),
+              mean_abs_error: mean(&errors),
+              rms_error: mean(&errors.iter().map(|e| e * e).collect::<Vec<f64>>()).sqrt(),
+              within_10: if errors.is_empty() {0.0} else {100.0 * errors.iter().filter(|e| **e <= 10.0).count() as f64 / errors.len() as f64},
+              correlation: correlation(&pairs),
+              final_price: pairs.last().map(|(price, _)| *price),
+              final_value: history.fundamentals.get(&stock_id).and_then(|values| values.last().cloned())}
+  }).collect()
+}
+
+//Pearson correlation, None without any variation to correlate.
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+  let xs: Vec<f64> = pairs.iter().map(|(x, _)| *x).collect();
+  let ys: Vec<f64> = pairs.iter().map(|(_, y)| *y).collect();
+  let (mx, my) = (mean(&xs), mean(&ys));
+  let covariance: f64 = pairs.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+  let vx: f64 = xs.iter().map(|x| (x - mx) * (x - mx)).sum();
+  let vy: f64 = ys.iter().map(|y| (y - my) * (y - my)).sum();
+  if vx > 0.0 && vy > 0.0 {Some(covariance / (vx * vy).sqrt())} else {None}
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::messages::{Side, TransactionRequest};
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+  }
+
+  #[test]
+  fn seeded_values_are_reproducible_and_at_least_one() {
+    //Starting near 1 with large moves, so some values have to be held at 1.
+    let config = FundamentalConfig {process: FundamentalProcess::JumpDiffusion, start: 2.0, volatility: 0.5, jump_probability: 0.5, jump_size: 1.0, ..Default::default()};
+    let mut first = MarketHistory::default();
+    let mut second = MarketHistory::default();
+    let mut other_seed = MarketHistory::default();
+    generate(&mut first, &config, 2, 1000, 3);
+    generate(&mut second, &config, 2, 1000, 3);
+    generate(&mut other_seed, &config, 2, 1000, 4);
+    assert_eq!(first.fundamentals, second.fundamentals);
+    assert_ne!(first.fundamentals, other_seed.fundamentals);
+    assert_eq!(first.fundamental_step, 100);
+    for stock_id in 0..2 {
+      let values = &first.fundamentals[&stock_id];
+      assert_eq!(values.len(), 1000 / 100 + 1);
+      assert_eq!(values[0], 2);
+      assert!(values.iter().all(|value| *value >= 1));
+    }
+  }
+
+  #[test]
+  fn tracking_compares_unit_prices_with_the_value_at_their_time() {
+    let mut history = MarketHistory::default();
+    history.fundamentals.insert(0, Arc::new(vec![100, 100, 200]));
+    history.fundamental_step = 10;
+    for (time, price, quantity) in [(5, 105, 1), (15, 160, 2), (25, 220, 1)] {
+      let buyer = TransactionRequest {transaction_id: 0, actor_id: 1, stock_id: 0, price, quantity};
+      let seller = TransactionRequest {transaction_id: 0, actor_id: 2, stock_id: 0, price, quantity};
+      history.record_trade(0, time, (buyer, seller), Side::Buy, 0, 0);
+    }
+    let tracked = tracking(&history);
+    assert_eq!(tracked.len(), 1);
+    let stock = &tracked[0];
+    //Unit prices 105, 80 and 220 against values 100, 100 and 200 are off by 5%, 20% and 10%.
+    assert_eq!(stock.trades, 3);
+    assert_close(stock.mean_abs_error, 35.0 / 3.0);
+    assert_close(stock.rms_error, (525.0f64 / 3.0).sqrt());
+    assert_close(stock.within_10, 200.0 / 3.0);
+    //Deviations from the means: prices -30, -55 and 85, values -100/3, -100/3 and 200/3.
+    assert_close(stock.correlation.unwrap(), 8500.0 / (11150.0f64 * 20000.0 / 3.0).sqrt());
+    assert_eq!(stock.final_price, Some(220.0));
+    assert_eq!(stock.final_value, Some(200));
+  }
+}
@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use crate::messages::{MarketHistory, Side, Trade, TransactionRequest};
+
+/*
+Saves the recorded trades of a MarketHistory and loads them back into a new one.
+Paths ending in .csv are written as CSV, anything else in a binary columnar format:
+
+  magic "WOWH", u32 version, u64 trade count, then one column after the other, each holding
+  a value for every trade in sequence order: seq, time, market_id, settlement_latency,
+  the buyer's transaction_id, actor_id, stock_id, price, quantity, the seller's
+  transaction_id, actor_id, stock_id, price, quantity, the fee (all u64) and the aggressor
+  (u8, 0 = buy). Integers are little endian. Version 1 files have no fee column.
+
+Candles and indicators are not stored, loading replays the trades to rebuild them.
+*/
+
+const MAGIC: &[u8; 4] = b"WOWH";
+const VERSION: u32 = 2;
+const CSV_HEADER: &str = "seq,time,market_id,settlement_latency,aggressor,\
+buyer_transaction_id,buyer_actor_id,buyer_stock_id,buyer_price,buyer_quantity,\
+seller_transaction_id,seller_actor_id,seller_stock_id,seller_price,seller_quantity,fee";
+const U64_COLUMNS: usize = 15;
+const V1_U64_COLUMNS: usize = 14; //Before the fee was recorded
+
+pub fn save_history(history: &MarketHistory, path: &str) -> io::Result<()> {
+  let trades = trades_in_order(history);
+  let mut writer = BufWriter::new(File::create(path)?);
+  if is_csv(path) {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for trade in trades.iter() {
+      let columns: Vec<String> = u64_columns(trade).iter().map(|value| value.to_string()).collect();
+      let (front, back) = columns.split_at(4);
+      writeln!(writer, "{},{},{}", front.join(","), side_name(trade.aggressor), back.join(","))?;
+    }
+  }
+  else {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(trades.len() as u64).to_le_bytes())?;
+    for column in 0..U64_COLUMNS {
+      for trade in trades.iter() {
+        writer.write_all(&u64_columns(trade)[column].to_le_bytes())?;
+      }
+    }
+    for trade in trades.iter() {
+      writer.write_all(&[if trade.aggressor == Side::Buy {0} else {1}])?;
+    }
+  }
+  writer.flush()
+}
+
+//Trades recorded during the new run are stamped after the last loaded one, see MarketHistory::time_offset.
+pub fn load_history(path: &str, candle_interval: usize) -> io::Result<MarketHistory> {
+  let mut trades = if is_csv(path) {read_csv(path)?} else {read_binary(path)?};
+  trades.sort_by_key(|trade| trade.seq);
+
+  let mut history = MarketHistory::new(candle_interval);
+  let mut end_time = 0;
+  for trade in trades {
+    let stock_id = trade.buyer.stock_id;
+    if !history.stocks.contains(&stock_id) {
+      history.stocks.push(stock_id);
+    }
+    end_time = end_time.max(trade.time + 1);
+    history.record_trade(trade.market_id, trade.time, (trade.buyer, trade.seller), trade.aggressor, trade.settlement_latency, trade.fee);
+  }
+  history.stocks.sort();
+  //Start the new run on a candle boundary so its candles line up with the loaded ones.
+  history.time_offset = end_time.div_ceil(history.candle_interval) * history.candle_interval;
+  Ok(history)
+}
+
+fn is_csv(path: &str) -> bool {
+  path.to_lowercase().ends_with(".csv")
+}
+
+fn trades_in_order(history: &MarketHistory) -> Vec<&Trade> {
+  let mut trades: Vec<&Trade> = history.history.values().flat_map(|trades| trades.iter()).collect();
+  trades.sort_by_key(|trade| trade.seq);
+  trades
+}
+
+fn u64_columns(trade: &Trade) -> [u64; U64_COLUMNS] {
+  [trade.seq as u64, trade.time as u64, trade.market_id as u64, trade.settlement_latency as u64,
+   trade.buyer.transaction_id as u64, trade.buyer.actor_id as u64, trade.buyer.stock_id as u64,
+   trade.buyer.price as u64, trade.buyer.quantity as u64,
+   trade.seller.transaction_id as u64, trade.seller.actor_id as u64, trade.seller.stock_id as u64,
+   trade.seller.price as u64, trade.seller.quantity as u64, trade.fee as u64]
+}
+
+//Columns in the order of u64_columns, a missing fee column counts as no fee.
+fn trade_from_columns(columns: &[usize], aggressor: Side) -> Trade {
+  Trade {seq: columns[0],
+         time: columns[1],
+         market_id: columns[2],
+         settlement_latency: columns[3],
+         buyer: TransactionRequest {transaction_id: columns[4], actor_id: columns[5], stock_id: columns[6], price: columns[7], quantity: columns[8]},
+         seller: TransactionRequest {transaction_id: columns[9], actor_id: columns[10], stock_id: columns[11], price: columns[12], quantity: columns[13]},
+         aggressor,
+         fee: columns.get(14).cloned().unwrap_or(0)}
+}
+
+fn side_name(side: Side) -> &'static str {
+  match side {
+    Side::Buy => "buy",
+    Side::Sell => "sell"
+  }
+}
+
+fn invalid(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_csv(path: &str) -> io::Result<Vec<Trade>> {
+  let reader = BufReader::new(File::open(path)?);
+  let mut trades = vec![];
+  for (i, line) in reader.lines().enumerate() {
+    let line = line?;
+    if i == 0 || line.trim().is_empty() {
+      continue;
+    }
+    let fields: Vec<&str> = line.trim().split(',').collect();
+    if fields.len() != U64_COLUMNS + 1 && fields.len() != V1_U64_COLUMNS + 1 {
+      return Err(invalid(format!("line {}: expected {} fields, found {}", i + 1, U64_COLUMNS + 1, fields.len())));
+    }
+    let aggressor = match fields[4] {
+      "buy" => Side::Buy,
+      "sell" => Side::Sell,
+      other => {return Err(invalid(format!("line {}: unknown aggressor {}", i + 1, other)));}
+    };
+    let mut columns = Vec::with_capacity(U64_COLUMNS);
+    for field in fields[..4].iter().chain(fields[5..].iter()) {
+      match field.parse() {
+        Ok(value) => {columns.push(value);},
+        Err(_) => {return Err(invalid(format!("line {}: {} is not a number", i + 1, field)));}
+      }
+    }
+    trades.push(trade_from_columns(&columns, aggressor));
+  }
+  Ok(trades)
+}
+
+fn read_binary(path: &str) -> io::Result<Vec<Trade>> {
+  let mut bytes = vec![];
+  File::open(path)?.read_to_end(&mut bytes)?;
+  if bytes.len() < 16 || &bytes[0..4] != MAGIC {
+    return Err(invalid(format!("{} is not a saved market history", path)));
+  }
+  let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+  let column_count = match version {
+    1 => V1_U64_COLUMNS,
+    VERSION => U64_COLUMNS,
+    _ => {return Err(invalid(format!("unsupported history version {}", version)));}
+  };
+  let count = read_u64(&bytes, 8) as usize;
+  if count.checked_mul(column_count * 8 + 1).and_then(|size| size.checked_add(16)) != Some(bytes.len()) {
+    return Err(invalid(format!("{} is truncated", path)));
+  }
+
+  let aggressors = 16 + count * column_count * 8;
+  let mut trades = Vec::with_capacity(count);
+  for row in 0..count {
+    let columns: Vec<usize> = (0..column_count).map(|column| read_u64(&bytes, 16 + (column * count + row) * 8) as usize).collect();
+    let aggressor = if bytes[aggressors + row] == 0 {Side::Buy} else {Side::Sell};
+    trades.push(trade_from_columns(&columns, aggressor));
+  }
+  Ok(trades)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+  let mut value = [0; 8];
+  value.copy_from_slice(&bytes[offset..offset + 8]);
+  u64::from_le_bytes(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("history_store_test_{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+  }
+
+  fn request(transaction_id: usize, actor_id: usize, stock_id: usize, price: usize, quantity: usize) -> TransactionRequest {
+    TransactionRequest {transaction_id, actor_id, stock_id, price, quantity}
+  }
+
+  fn sample_history() -> MarketHistory {
+    let mut history = MarketHistory::new(100);
+    history.record_trade(0, 10, (request(1, 3, 0, 40, 2), request(7, 4, 0, 40, 2)), Side::Buy, 120, 0);
+    history.record_trade(1, 150, (request(2, 3, 1, 99, 3), request(8, 5, 1, 99, 3)), Side::Sell, 0, 2);
+    history.record_trade(0, 230, (request(3, 6, 0, 45, 1), request(9, 4, 0, 45, 1)), Side::Sell, 35, 1);
+    history
+  }
+
+  fn assert_round_trip(path: &str) {
+    let history = sample_history();
+    save_history(&history, path).unwrap();
+    let loaded = load_history(path, 100);
+    fs::remove_file(path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(trades_in_order(&loaded), trades_in_order(&history));
+    assert_eq!(loaded.stocks, vec![0, 1]);
+    assert_eq!(loaded.time_offset, 300);
+    assert_eq!(loaded.candles(0).len(), 2);
+  }
+
+  #[test]
+  fn csv_round_trip() {
+    assert_round_trip(&temp_path("round_trip.csv"));
+  }
+
+  #[test]
+  fn binary_round_trip() {
+    assert_round_trip(&temp_path("round_trip.wowh"));
+  }
+
+  #[test]
+  fn version_1_files_load_without_fees() {
+    //Two trades, columns one after the other without the fee, then the aggressors.
+    let columns: [[u64; 2]; V1_U64_COLUMNS] = [[0, 1], [10, 20], [0, 0], [5, 6],
+                                               [1, 2], [3, 3], [0, 0], [40, 45], [2, 1],
+                                               [7, 8], [4, 4], [0, 0], [40, 45], [2, 1]];
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    bytes.extend_from_slice(&2u64.to_le_bytes());
+    for column in columns.iter() {
+      for value in column.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+      }
+    }
+    bytes.extend_from_slice(&[1, 0]);
+    let path = temp_path("version_1.wowh");
+    fs::write(&path, &bytes).unwrap();
+    let loaded = load_history(&path, 100);
+    fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    let trades = trades_in_order(&loaded);
+    assert_eq!(trades.len(), 2);
+    assert_eq!(*trades[0], Trade {seq: 0, time: 10, market_id: 0, buyer: request(1, 3, 0, 40, 2), seller: request(7, 4, 0, 40, 2),
+                                  aggressor: Side::Sell, settlement_latency: 5, fee: 0});
+    assert_eq!((trades[1].time, trades[1].price(), trades[1].aggressor, trades[1].fee), (20, 45, Side::Buy, 0));
+  }
+
+  #[test]
+  fn truncated_file_is_an_error() {
+    let path = temp_path("truncated.wowh");
+    save_history(&sample_history(), &path).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.pop();
+    fs::write(&path, &bytes).unwrap();
+    let loaded = load_history(&path, 100);
+    fs::remove_file(&path).unwrap();
+    match loaded {
+      Ok(_) => panic!("a truncated history was loaded"),
+      Err(error) => assert!(error.to_string().contains("truncated"), "{}", error)
+    }
+  }
+}
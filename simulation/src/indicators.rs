@@ -0,0 +1,348 @@
+use std::collections::VecDeque;
+
+/*
+Technical indicators over the traded prices of one stock. MarketHistory keeps one
+Indicators per stock and feeds it every committed trade, so each indicator is updated
+in constant time and strategies only read the current values.
+Every getter returns None until enough trades have been seen to fill its period.
+*/
+
+#[derive(Clone, Debug)]
+pub struct IndicatorConfig {
+  pub sma_period: usize,
+  pub ema_period: usize,
+  pub rsi_period: usize,
+  pub bollinger_period: usize,
+  pub bollinger_width: f64, //Standard deviations between the middle and the outer bands
+  pub macd_fast: usize,
+  pub macd_slow: usize,
+  pub macd_signal: usize,
+  pub volatility_period: usize //Number of trade to trade returns
+}
+
+impl Default for IndicatorConfig {
+  fn default() -> IndicatorConfig {
+    IndicatorConfig {sma_period: 20,
+                     ema_period: 20,
+                     rsi_period: 14,
+                     bollinger_period: 20,
+                     bollinger_width: 2.0,
+                     macd_fast: 12,
+                     macd_slow: 26,
+                     macd_signal: 9,
+                     volatility_period: 20}
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bands {
+  pub lower: f64,
+  pub middle: f64,
+  pub upper: f64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Macd {
+  pub macd: f64,
+  pub signal: f64,
+  pub histogram: f64
+}
+
+#[derive(Clone, Debug)]
+pub struct Indicators {
+  sma: RollingWindow,
+  ema: Ema,
+  bollinger: RollingWindow,
+  bollinger_width: f64,
+  rsi: Rsi,
+  macd_fast: Ema,
+  macd_slow: Ema,
+  macd_signal: Ema,
+  returns: RollingWindow,
+  last_price: Option<f64>,
+  traded_value: f64,
+  traded_volume: usize
+}
+
+impl Indicators {
+  pub fn new(config: &IndicatorConfig) -> Indicators {
+    Indicators {sma: RollingWindow::new(config.sma_period),
+                ema: Ema::new(config.ema_period),
+                bollinger: RollingWindow::new(config.bollinger_period),
+                bollinger_width: config.bollinger_width,
+                rsi: Rsi::new(config.rsi_period),
+                macd_fast: Ema::new(config.macd_fast),
+                macd_slow: Ema::new(config.macd_slow),
+                macd_signal: Ema::new(config.macd_signal),
+                returns: RollingWindow::new(config.volatility_period),
+                last_price: None,
+                traded_value: 0.0,
+                traded_volume: 0}
+  }
+
+  //unit_price is the price of one share, the trade's lot total divided by its quantity.
+  pub fn update(&mut self, unit_price: f64, quantity: usize) {
+    self.sma.push(unit_price);
+    self.ema.push(unit_price);
+    self.bollinger.push(unit_price);
+    self.macd_fast.push(unit_price);
+    self.macd_slow.push(unit_price);
+    if let (Some(fast), Some(slow)) = (self.macd_fast.value, self.macd_slow.value) {
+      self.macd_signal.push(fast - slow);
+    }
+    if let Some(last) = self.last_price {
+      self.rsi.push(unit_price - last);
+      //A trade at a price of zero has no log return.
+      if last > 0.0 && unit_price > 0.0 {
+        self.returns.push((unit_price / last).ln());
+      }
+    }
+    self.last_price = Some(unit_price);
+    //The lot total again, so vwap is the traded value over the shares traded.
+    self.traded_value += unit_price * quantity as f64;
+    self.traded_volume += quantity;
+  }
+
+  pub fn sma(&self) -> Option<f64> {
+    self.sma.mean()
+  }
+
+  pub fn ema(&self) -> Option<f64> {
+    self.ema.value
+  }
+
+  //Volume weighted average price of one share over every trade seen so far.
+  pub fn vwap(&self) -> Option<f64> {
+    if self.traded_volume == 0 {
+      None
+    }
+    else {
+      Some(self.traded_value / self.traded_volume as f64)
+    }
+  }
+
+  //Wilder's relative strength index, 0 to 100.
+  pub fn rsi(&self) -> Option<f64> {
+    self.rsi.value()
+  }
+
+  pub fn bollinger(&self) -> Option<Bands> {
+    match (self.bollinger.mean(), self.bollinger.std_dev()) {
+      (Some(middle), Some(std_dev)) => Some(Bands {lower: middle - self.bollinger_width * std_dev,
+                                                   middle,
+                                                   upper: middle + self.bollinger_width * std_dev}),
+      _ => None
+    }
+  }
+
+  pub fn macd(&self) -> Option<Macd> {
+    match (self.macd_fast.value, self.macd_slow.value, self.macd_signal.value) {
+      (Some(fast), Some(slow), Some(signal)) => Some(Macd {macd: fast - slow, signal, histogram: fast - slow - signal}),
+      _ => None
+    }
+  }
+
+  //Standard deviation of the log returns between consecutive trades.
+  pub fn volatility(&self) -> Option<f64> {
+    self.returns.std_dev()
+  }
+}
+
+//Last period values with running sums for the mean and standard deviation.
+#[derive(Clone, Debug)]
+struct RollingWindow {
+  period: usize,
+  values: VecDeque<f64>,
+  sum: f64,
+  sum_of_squares: f64
+}
+
+impl RollingWindow {
+  fn new(period: usize) -> RollingWindow {
+    RollingWindow {period: period.max(1), values: VecDeque::new(), sum: 0.0, sum_of_squares: 0.0}
+  }
+
+  fn push(&mut self, value: f64) {
+    self.values.push_back(value);
+    self.sum += value;
+    self.sum_of_squares += value * value;
+    if self.values.len() > self.period {
+      if let Some(old) = self.values.pop_front() {
+        self.sum -= old;
+        self.sum_of_squares -= old * old;
+      }
+    }
+  }
+
+  fn is_full(&self) -> bool {
+    self.values.len() == self.period
+  }
+
+  fn mean(&self) -> Option<f64> {
+    if self.is_full() {Some(self.sum / self.period as f64)} else {None}
+  }
+
+  fn std_dev(&self) -> Option<f64> {
+    //Running sums can leave a tiny negative variance behind.
+    self.mean().map(|mean| (self.sum_of_squares / self.period as f64 - mean * mean).max(0.0).sqrt())
+  }
+}
+
+//Seeded with the simple average of its first period values.
+#[derive(Clone, Debug)]
+struct Ema {
+  period: usize,
+  seed: f64,
+  count: usize,
+  value: Option<f64>
+}
+
+impl Ema {
+  fn new(period: usize) -> Ema {
+    Ema {period: period.max(1), seed: 0.0, count: 0, value: None}
+  }
+
+  fn push(&mut self, value: f64) {
+    match self.value {
+      Some(current) => {
+        let alpha = 2.0 / (self.period as f64 + 1.0);
+        self.value = Some(current + alpha * (value - current));
+      },
+      None => {
+        self.seed += value;
+        self.count += 1;
+        if self.count == self.period {
+          self.value = Some(self.seed / self.period as f64);
+        }
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+struct Rsi {
+  period: usize,
+  count: usize,
+  average_gain: f64,
+  average_loss: f64
+}
+
+impl Rsi {
+  fn new(period: usize) -> Rsi {
+    Rsi {period: period.max(1), count: 0, average_gain: 0.0, average_loss: 0.0}
+  }
+
+  fn push(&mut self, change: f64) {
+    let gain = change.max(0.0);
+    let loss = (-change).max(0.0);
+    let period = self.period as f64;
+    if self.count < self.period {
+      //Plain average over the first period changes, Wilder's smoothing afterwards.
+      self.average_gain += gain / period;
+      self.average_loss += loss / period;
+    }
+    else {
+      self.average_gain = (self.average_gain * (period - 1.0) + gain) / period;
+      self.average_loss = (self.average_loss * (period - 1.0) + loss) / period;
+    }
+    self.count += 1;
+  }
+
+  fn value(&self) -> Option<f64> {
+    if self.count < self.period {
+      None
+    }
+    else if self.average_gain == 0.0 && self.average_loss == 0.0 {
+      Some(50.0)
+    }
+    else if self.average_loss == 0.0 {
+      Some(100.0)
+    }
+    else {
+      Some(100.0 - 100.0 / (1.0 + self.average_gain / self.average_loss))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  //Reference values computed independently from the textbook definitions over this series.
+  const PRICES: [usize; 20] = [10, 12, 11, 13, 15, 14, 16, 18, 17, 15, 14, 16, 19, 21, 20, 22, 21, 19, 18, 20];
+
+  fn config() -> IndicatorConfig {
+    IndicatorConfig {sma_period: 5, ema_period: 5, rsi_period: 5, bollinger_period: 5, bollinger_width: 2.0,
+                     macd_fast: 3, macd_slow: 6, macd_signal: 3, volatility_period: 5}
+  }
+
+  fn feed(prices: &[usize]) -> Indicators {
+    let mut indicators = Indicators::new(&config());
+    for price in prices {
+      indicators.update(*price as f64, 1);
+    }
+    indicators
+  }
+
+  fn assert_close(actual: Option<f64>, expected: f64) {
+    match actual {
+      Some(actual) => assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected),
+      None => panic!("no value, expected {}", expected)
+    }
+  }
+
+  #[test]
+  fn nothing_before_the_period_is_full() {
+    let indicators = feed(&PRICES[..4]);
+    assert_eq!(indicators.sma(), None);
+    assert_eq!(indicators.ema(), None);
+    assert_eq!(indicators.rsi(), None);
+    assert_eq!(indicators.bollinger(), None);
+    assert_eq!(indicators.macd(), None);
+    assert_eq!(indicators.volatility(), None);
+  }
+
+  #[test]
+  fn moving_averages() {
+    let indicators = feed(&PRICES);
+    assert_close(indicators.sma(), 20.0);
+    assert_close(indicators.ema(), 19.466449228502213);
+    assert_close(indicators.vwap(), 16.55);
+  }
+
+  #[test]
+  fn vwap_weights_unit_prices_by_quantity() {
+    let mut indicators = Indicators::new(&config());
+    indicators.update(4.0, 10);
+    indicators.update(6.0, 30);
+    assert_close(indicators.vwap(), 5.5);
+  }
+
+  #[test]
+  fn wilder_rsi() {
+    assert_close(feed(&PRICES).rsi(), 58.33304037609927);
+    assert_close(feed(&[1, 2, 3, 4, 5, 6]).rsi(), 100.0);
+    assert_close(feed(&[7, 7, 7, 7, 7, 7]).rsi(), 50.0);
+  }
+
+  #[test]
+  fn bollinger_bands() {
+    let bands = feed(&PRICES).bollinger().unwrap();
+    assert_close(Some(bands.lower), 17.17157287525381);
+    assert_close(Some(bands.middle), 20.0);
+    assert_close(Some(bands.upper), 22.82842712474619);
+  }
+
+  #[test]
+  fn macd_and_signal() {
+    let macd = feed(&PRICES).macd().unwrap();
+    assert_close(Some(macd.macd), 0.09935335296185244);
+    assert_close(Some(macd.signal), 0.19617916062838625);
+    assert_close(Some(macd.histogram), -0.09682580766653381);
+  }
+
+  #[test]
+  fn volatility_of_log_returns() {
+    assert_close(feed(&PRICES).volatility(), 0.08401064644591506);
+  }
+}
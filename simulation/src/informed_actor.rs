@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::stats::mean;
+use crate::fundamental::normal;
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::trading::RestingOrders;
+
+/*
+Trades on the hidden fundamental value (see fundamental.rs), which it observes with noise:
+once per fundamental step its estimate of each stock is the value times one plus a normal
+draw scaled by noise. When the mean of the last window unit prices is more than edge below
+its estimate it bids edge below the estimate with position_fraction of its cash, when it is
+more than edge above it offers everything it holds edge above the estimate. A stock that
+hasn't traded yet is bought. Stocks without a fundamental value are left alone.
+
+Orders are kept resting by trading::RestingOrders.
+*/
+
+#[derive(Clone)]
+pub struct InformedConfig {
+  pub noise: f64, //Standard deviation of the observation error, as a fraction of the value
+  pub edge: f64, //Fraction of the estimate a price has to be off by before it trades
+  pub position_fraction: f64, //Share of the cash put into one stock
+  pub window: usize //Trades averaged into the price it compares with
+}
+
+impl Default for InformedConfig {
+  fn default() -> InformedConfig {
+    InformedConfig {noise: 0.05, edge: 0.05, position_fraction: 0.5, window: 5}
+  }
+}
+
+struct Estimates {
+  step: Option<usize>,
+  values: HashMap<usize, f64> //stock_id, estimated value
+}
+
+pub fn start_informed_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, config: InformedConfig, seed: u64, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Informed Actor {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut orders = RestingOrders::default();
+  let mut estimates = Estimates {step: None, values: HashMap::new()};
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(now, _)) => {
+            observe(&actor, &config, now, &mut rng, &mut estimates);
+            trade(&actor, &config, &estimates, &mut orders);
+          },
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Informed Actor) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Informed Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+//A new noisy look at every value once the fundamental step changes, the same estimate until then.
+fn observe(actor: &Actor, config: &InformedConfig, now: usize, rng: &mut StdRng, estimates: &mut Estimates) {
+  let history = actor.history.snapshot();
+  let step = now / history.fundamental_step;
+  if estimates.step == Some(step) {
+    return;
+  }
+  estimates.step = Some(step);
+  estimates.values.clear();
+  for stock_id in history.stocks.iter() {
+    if let Some(value) = history.fundamental_value(*stock_id, now) {
+      estimates.values.insert(*stock_id, (value as f64 * (1.0 + config.noise * normal(rng))).max(1.0));
+    }
+  }
+}
+
+fn trade(actor: &Actor, config: &InformedConfig, estimates: &Estimates, orders: &mut RestingOrders) {
+  if has_pending_transaction(actor) {
+    return;
+  }
+  let history = actor.history.snapshot();
+  let mut cash = actor.money;
+  for stock_id in history.stocks.iter() {
+    let estimate = match estimates.values.get(stock_id) {
+      Some(estimate) => *estimate,
+      None => {
+        orders.revoke(actor, *stock_id);
+        continue;
+      }
+    };
+    let prices = history.recent_unit_prices(*stock_id, config.window);
+    let price = if prices.is_empty() {None} else {Some(mean(&prices))};
+    let held = *actor.stocks.get(stock_id).unwrap_or(&0);
+    let bid = (estimate * (1.0 - config.edge)).floor() as usize;
+    let ask = ((estimate * (1.0 + config.edge)).ceil() as usize).max(1);
+
+    match price {
+      Some(price) if held > 0 && price > ask as f64 => {
+        orders.sell_at_most(actor, *stock_id, ask, held);
+        continue;
+      },
+      Some(price) if price >= bid as f64 => {},
+      _ => {
+        let shares = ((cash as f64 * config.position_fraction) as usize).checked_div(bid).unwrap_or(0);
+        if shares > 0 {
+          orders.buy_at_most(actor, *stock_id, bid, shares);
+          cash = cash.saturating_sub(orders.resting_bid(*stock_id).unwrap_or(0));
+          continue;
+        }
+      }
+    }
+    orders.revoke(actor, *stock_id);
+  }
+}
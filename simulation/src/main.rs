@@ -1,10 +1,9 @@
-#![feature(core, io, rand, std_misc)]
-#![allow(deprecated)]
-use std::collections::HashMap;
-use std::sync::mpsc::{Sender, Receiver, channel};
-use std::thread::Thread;
-use std::old_io::timer;
+use std::env;
+use std::process;
+use std::sync::Arc;
 use std::time::Duration;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 
 pub mod messages;
 pub mod market;
@@ -16,130 +15,332 @@ pub mod scripted_actor;
 pub mod random_actor;
 pub mod dummy_actor_1;
 pub mod dummy_actor_2;
+pub mod supervisor;
+pub mod event_log;
+pub mod replay;
+pub mod report;
+pub mod indicators;
+pub mod history_store;
+pub mod price_data;
+pub mod append_log;
+pub mod benchmark;
+pub mod pool;
+pub mod portfolio;
+pub mod scenario;
+pub mod batch;
+pub mod stats;
+pub mod sweep;
+pub mod market_stats;
+pub mod market_maker;
+pub mod trading;
+pub mod momentum_actor;
+pub mod mean_reversion_actor;
+pub mod zi_trader;
+pub mod fundamental;
+pub mod informed_actor;
+pub mod script;
+pub mod strategy_actor;
+pub mod environment;
 
-use messages::*;
-use market::*;
-use actor::*;
-use corporate_actor::*;
-use smarter_actor::*;
-use scripted_actor::*;
-use random_actor::*;
-use dummy_actor_1::*;
-use dummy_actor_2::*;
+use crate::messages::*;
+use event_log::EventLog;
+use scenario::{run_scenario, Scenario};
 
 fn main() {
-  //tx: clone for actors        rx: owned by market
-  let (tx_market, rx_market): (Sender<MarketMessages>, Receiver<MarketMessages>) = channel();
-
-  let standard_actor_count = 5;
-  let corporate_actor_count = 5;
-  let scripted_actor_count = 10;
-  let smarter_actor_count = 5;
-  let random_actor_count = 15;
-  let dummy_actor_1_count = 4;
-  let dummy_actor_2_count = 4;
+  let args: Vec<String> = env::args().collect();
+  if let Some(path) = flag_value(&args, "--replay") {
+    let replay_path = flag_value(&args, "--event-log").unwrap_or(path.clone() + ".replay");
+    match replay::replay(&path, &replay_path) {
+      Ok(true) => {},
+      Ok(false) => {process::exit(1);},
+      Err(e) => {
+        println!("ERROR: could not replay {}: {}", path, e);
+        process::exit(1);
+      }
+    }
+    return;
+  }
+  if args.iter().any(|arg| arg == "--bench-history") {
+    benchmark::bench_history(flag_optional_usize(&args, "--bench-history", 1000));
+    return;
+  }
+  if args.iter().any(|arg| arg == "--bench-matching") {
+    benchmark::bench_matching();
+    return;
+  }
+  let event_log = match flag_value(&args, "--event-log") {
+    Some(path) => {
+      match EventLog::create(&path) {
+        Ok(log) => Some(log),
+        Err(e) => {
+          println!("ERROR: could not create event log {}: {}", path, e);
+          return;
+        }
+      }
+    },
+    None => None
+  };
 
-  //TODO: with spawning multiple markets make this a for loop.
-  let tx_market_clone = tx_market.clone();
-  Thread::spawn(move || {
-    market::start_market(0, tx_market_clone, rx_market, corporate_actor_count);});
+  let mut scenario = Scenario::default();
+  scenario.pooled_random_actors = flag_usize(&args, "--pooled-actors", 0);
+  scenario.workers = flag_usize(&args, "--workers", scenario.workers);
+  scenario.market_makers = flag_usize(&args, "--market-makers", 0);
+  scenario.momentum_actors = flag_usize(&args, "--momentum-actors", scenario.momentum_actors);
+  scenario.mean_reversion_actors = flag_usize(&args, "--mean-reversion-actors", 0);
+  scenario.mean_reversion.window = flag_usize(&args, "--reversion-window", scenario.mean_reversion.window);
+  scenario.mean_reversion.buy_threshold = flag_f64(&args, "--reversion-buy", scenario.mean_reversion.buy_threshold);
+  scenario.mean_reversion.sell_threshold = flag_f64(&args, "--reversion-sell", scenario.mean_reversion.sell_threshold);
+  scenario.pool_round = Duration::from_millis(flag_usize(&args, "--pool-round", 100) as u64);
+  scenario.seed = match flag_value(&args, "--seed") {
+    Some(_) => flag_usize(&args, "--seed", 0) as u64,
+    None => rand::random()
+  };
+  //A sweep takes comma separated lists for these instead, see sweep.rs.
+  let sweep_path = flag_value(&args, "--sweep");
+  if sweep_path.is_none() {
+    scenario.starting_money = flag_usize(&args, "--cash", scenario.starting_money);
+    scenario.corporate_starting_quantity = flag_usize(&args, "--corporate-quantity", scenario.corporate_starting_quantity);
+    scenario.random_actors = flag_usize(&args, "--random-actors", scenario.random_actors);
+    scenario.tick = flag_usize(&args, "--tick", scenario.tick);
+    scenario.fee_bps = flag_usize(&args, "--fee-bps", scenario.fee_bps);
+  }
+  let zi_traders = flag_usize(&args, "--zi-c", 0);
+  scenario.zi.buyers = zi_traders;
+  scenario.zi.sellers = zi_traders;
+  if args.iter().any(|arg| arg == "--zi-only") {
+    scenario.only_zi();
+  }
+  if let Some(name) = flag_value(&args, "--fundamental") {
+    match fundamental::FundamentalProcess::parse(&name) {
+      Some(process) => {
+        let defaults = fundamental::FundamentalConfig::default();
+        scenario.fundamental = Some(fundamental::FundamentalConfig {process,
+                                                                    drift: flag_f64(&args, "--fundamental-drift", defaults.drift),
+                                                                    volatility: flag_f64(&args, "--fundamental-volatility", defaults.volatility),
+                                                                    step: flag_usize(&args, "--fundamental-step", defaults.step).max(1),
+                                                                    ..defaults});
+      },
+      None => {
+        println!("ERROR: --fundamental expects random-walk or jump-diffusion, got {}", name);
+        process::exit(1);
+      }
+    }
+    //Generating the values would overwrite the closes --follow-prices set them to.
+    if args.iter().any(|arg| arg == "--follow-prices") {
+      println!("ERROR: --fundamental and --follow-prices both set the fundamental values, use one of them");
+      process::exit(1);
+    }
+  }
+  scenario.informed_actors = flag_usize(&args, "--informed-actors", 0);
+  if let Some(path) = flag_value(&args, "--script") {
+    match script::load_script(&path) {
+      Ok(loaded) => {
+        println!("Loaded {} rules from {}", loaded.rules.len(), path);
+        scenario.script = Some(Arc::new(loaded));
+      },
+      Err(e) => {
+        println!("ERROR: could not load script {}", e);
+        process::exit(1);
+      }
+    }
+  }
+  scenario.scripted_actors = flag_usize(&args, "--scripted-actors", scenario.scripted_actors);
+  if let Some(path) = flag_value(&args, "--strategy") {
+    match strategy_actor::load_strategy(&path) {
+      Ok(source) => {
+        scenario.strategy = Some(Arc::new(source));
+        scenario.strategy_actors = flag_usize(&args, "--strategy-actors", 1);
+      },
+      Err(e) => {
+        println!("ERROR: could not load strategy {}", e);
+        process::exit(1);
+      }
+    }
+  }
 
-  let mut markets = HashMap::new();
-  markets.insert(0, tx_market.clone());
+  let candle_interval = flag_usize(&args, "--candle-interval", DEFAULT_CANDLE_INTERVAL);
+  let mut history = match flag_value(&args, "--load-history") {
+    Some(path) => {
+      match history_store::load_history(&path, candle_interval) {
+        Ok(loaded) => {
+          println!("Loaded {} trades from {}", loaded.next_seq, path);
+          loaded
+        },
+        Err(e) => {
+          println!("ERROR: could not load history from {}: {}", path, e);
+          return;
+        }
+      }
+    },
+    None => MarketHistory::new(candle_interval)
+  };
+  if let Some(dir) = flag_value(&args, "--price-data") {
+    let scale = match flag_value(&args, "--price-scale") {
+      Some(value) => match value.parse() {
+        Ok(scale) => scale,
+        Err(_) => {
+          println!("ERROR: --price-scale expects a number, got {}", value);
+          process::exit(1);
+        }
+      },
+      None => 1.0
+    };
+    let follow_fundamental = args.iter().any(|arg| arg == "--follow-prices");
+    match price_data::seed_history(&mut history, &dir, scenario.stock_count(), scale, follow_fundamental) {
+      Ok(rows) => {println!("Seeded {} price rows from {}", rows, dir);},
+      Err(e) => {
+        println!("ERROR: could not load price data from {}: {}", dir, e);
+        return;
+      }
+    }
+  }
 
-  let mut actors_with_timers = vec![];
+  if let Some(path) = sweep_path {
+    let grid = sweep::SweepGrid {starting_money: flag_list(&args, "--cash"),
+                                 corporate_starting_quantity: flag_list(&args, "--corporate-quantity"),
+                                 random_actors: flag_list(&args, "--random-actors"),
+                                 tick: flag_list(&args, "--tick"),
+                                 fee_bps: flag_list(&args, "--fee-bps")};
+    match sweep::run_sweep(&grid, &scenario, flag_optional_usize(&args, "--runs", 1).max(1), &history, &path) {
+      Ok(rows) => {println!("Wrote {} runs to {}", rows, path);},
+      Err(e) => {
+        println!("ERROR: could not write sweep results to {}: {}", path, e);
+        process::exit(1);
+      }
+    }
+    return;
+  }
+  //Episodes of the environment with a random policy, a baseline for learning agents.
+  if args.iter().any(|arg| arg == "--environment") {
+    let episodes = flag_optional_usize(&args, "--environment", 1).max(1);
+    let mut env = environment::Environment::new(scenario.clone(), history, environment::EnvironmentConfig::default());
+    for episode in 0..episodes {
+      let seed = scenario.seed.wrapping_add(episode as u64);
+      let mut rng = StdRng::seed_from_u64(seed);
+      let mut observation = env.reset(seed);
+      let (mut steps, mut total) = (0, 0.0);
+      loop {
+        let step = env.step(environment::random_actions(&observation, &mut rng));
+        steps += 1;
+        total += step.reward;
+        observation = step.observation;
+        if step.done {
+          break;
+        }
+      }
+      let net_worth = env.net_worth().unwrap_or(0.0);
+      env.close();
+      println!("Episode {}/{} (seed {}): {} steps, reward {:.2}, net worth {:.2}", episode + 1, episodes, seed, steps, total, net_worth);
+    }
+    return;
+  }
+  if args.iter().any(|arg| arg == "--runs") {
+    let runs = flag_optional_usize(&args, "--runs", 10).max(1);
+    let results = batch::run_batch(&scenario, runs, &history);
+    report::print_leaderboard(&batch::leaderboard(&results), runs);
+    return;
+  }
 
+  println!("Seed: {}", scenario.seed);
+  let result = run_scenario(&scenario, history, event_log);
 
-  let mut current_id = 0;
-  //TODO make more stocks and add history so actors can query on it.
-  for _ in 0..standard_actor_count {
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_actor(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  println!("\n\nFinal Result\n------------");
+  for status in result.responses.iter() {
+    if !status.is_empty() {
+      println!("{}", status);
+    }
   }
-
-  for id in 0..corporate_actor_count {
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_corporate_actor(current_id, m, id, 100, actor_tx, actor_rx);});//start a corporate actor with 100 of stock 0
-    current_id += 1;
+  for name in result.failed.iter() {
+    println!("ERROR: {} exited with a panic", name);
   }
 
-  for _ in 0..scripted_actor_count {
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_scripted_actor(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  let h = result.history;
+  report::print_candles(&h);
+  report::print_indicators(&h);
+  report::print_market_stats(&market_stats::summarize(&result.market_stats, &h));
+  if !h.fundamentals.is_empty() {
+    report::print_tracking(&fundamental::tracking(&h));
   }
-
-  for _ in 0..smarter_actor_count{
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_smarter_actor(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  if !result.zi_traders.is_empty() {
+    report::print_efficiency(&zi_trader::efficiency(&result.zi_traders, &h, scenario.zi.stock_id));
   }
-
-  for _ in 0..random_actor_count{
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_random_actor(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  let valuations = portfolio::value_portfolios(&result.endowments, &h);
+  report::print_portfolios(&valuations, &portfolio::summarize_kinds(&valuations));
+  if let Some(path) = flag_value(&args, "--candles") {
+    if let Err(e) = report::write_candles_csv(&h, &path) {
+      println!("ERROR: could not write candles to {}: {}", path, e);
+    }
   }
-
-  for _ in 0..dummy_actor_1_count{
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_dummy_actor_1(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  if let Some(path) = flag_value(&args, "--tick-stats") {
+    if let Err(e) = report::write_tick_stats_csv(&result.market_stats, &path) {
+      println!("ERROR: could not write tick statistics to {}: {}", path, e);
+    }
   }
-
-  for _ in 0..dummy_actor_2_count{
-    let m = markets.clone();
-    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
-    actors_with_timers.push(actor_tx.clone());
-    Thread::spawn(move || {start_dummy_actor_2(current_id, m, actor_tx, actor_rx);});
-    current_id += 1;
+  if let Some(path) = flag_value(&args, "--save-history") {
+    if let Err(e) = history_store::save_history(&h, &path) {
+      println!("ERROR: could not save history to {}: {}", path, e);
+    }
   }
+}
 
-  let tick = 100;
-  for t in 0..248 {
-    for tx in actors_with_timers.iter() {
-      tx.send(ActorMessages::Time(t * tick, 247 * tick)).unwrap();
-    }
-    timer::sleep(Duration::milliseconds(tick as i64));
+fn flag_usize(args: &[String], flag: &str, default: usize) -> usize {
+  match flag_value(args, flag) {
+    Some(value) => {
+      match value.parse() {
+        Ok(number) => number,
+        Err(_) => {
+          println!("ERROR: {} expects a number, got {}", flag, value);
+          process::exit(1);
+        }
+      }
+    },
+    None => default
   }
+}
 
-  let (my_tx, my_rx): (Sender<(usize, String)>, Receiver<(usize, String)>) = channel();
-  for tx in actors_with_timers.iter() {
-    tx.send(ActorMessages::Stop(my_tx.clone())).unwrap();
+fn flag_f64(args: &[String], flag: &str, default: f64) -> f64 {
+  match flag_value(args, flag) {
+    Some(value) => {
+      match value.parse() {
+        Ok(number) => number,
+        Err(_) => {
+          println!("ERROR: {} expects a number, got {}", flag, value);
+          process::exit(1);
+        }
+      }
+    },
+    None => default
   }
+}
 
-  let mut responses: Vec<String> = Vec::with_capacity(actors_with_timers.len());
-  for _ in 0..actors_with_timers.len() {
-    responses.push("".to_string());
+//For flags whose number may be left out, e.g. --runs or --runs 20. A following flag is not taken as the value.
+fn flag_optional_usize(args: &[String], flag: &str, default: usize) -> usize {
+  match args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)) {
+    Some(value) if !value.starts_with("--") => flag_usize(args, flag, default),
+    _ => default
   }
+}
 
-  let mut count = 0;
-  loop {
-    match my_rx.recv().unwrap() {
-      (id, status) => {
-        responses[id] = status;
-        count += 1;
-        if responses.len() == count {
-          break;
+//Comma separated numbers following flag, e.g. --tick 50,100,200
+fn flag_list(args: &[String], flag: &str) -> Vec<usize> {
+  match flag_value(args, flag) {
+    Some(value) => {
+      value.split(',').map(|item| match item.trim().parse() {
+        Ok(number) => number,
+        Err(_) => {
+          println!("ERROR: {} expects comma separated numbers, got {}", flag, value);
+          process::exit(1);
         }
-      }
-    }
+      }).collect()
+    },
+    None => vec![]
   }
-  println!("\n\nFinal Result\n------------");
-  for status in responses.iter() {
-    println!("{}", status);
+}
+
+//Returns the argument following flag, e.g. --event-log events.jsonl
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+  match args.iter().position(|arg| arg == flag) {
+    Some(i) => args.get(i + 1).cloned(),
+    None => None
   }
 }
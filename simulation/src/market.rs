@@ -1,58 +1,95 @@
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
 use std::sync::mpsc::{Sender, Receiver, channel};
-use std::sync::{Arc, Mutex};
-use std::thread::Thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::cmp;
+use std::time::Instant;
 
-use messages::{ActorMessages, MarketMessages, MarketHistory, MoneyRequest, StockRequest, TransactionRequest, TellerMessages};
-use messages::MarketMessages::{SellRequest, BuyRequest, Commit, Cancel, RegisterActor, MatchRequest, RequestActivityCount, RevokeRequest};
-use messages::ActorMessages::{AbortTransaction, CommitTransaction, History};
-use messages::TellerMessages::{RequestCount};
-use teller::*;
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory, MoneyRequest, StockRequest, TransactionRequest, TellerMessages, Side};
+use crate::messages::MarketMessages::{SellRequest, BuyRequest, Commit, Cancel, RegisterActor, MatchRequest, RequestActivityCount, RevokeRequest, Shutdown, ActorCrashed, Time, Quote};
+use crate::messages::ActorMessages::{AbortTransaction, CommitTransaction, History};
+use crate::messages::TellerMessages::{RequestCount};
+use crate::teller::*;
+use crate::event_log::{Event, EventLog};
+use crate::market_stats::MarketStats;
 
 struct Market {
   id: usize,
   tellers: HashMap<usize, Sender<TellerMessages>>,
   actors: HashMap<usize, Sender<ActorMessages>>,
   active_transactions: Vec<(TransactionRequest, TransactionRequest)>,
-  pending_transactions: Vec<(TransactionRequest, TransactionRequest)>,
+  pending_transactions: Vec<(TransactionRequest, TransactionRequest, Instant, Side)>, //Buyer's, seller's request, when it was matched, aggressor
+  match_details: HashMap<usize, (Instant, Side)>, //Buyer's actor id of an active transaction, (when it was matched, aggressor)
   committed_actors: Vec<usize>,
-  history: Arc<Mutex<MarketHistory>>
+  unanswered_requests: HashMap<usize, usize>, //actor_id, money and stock requests it has not answered yet
+  history: SharedHistory,
+  open: bool,
+  teller_shutdown: Arc<AtomicBool>,
+  current_time: usize,
+  fee_bps: usize, //Fee on every trade in basis points of its price, paid by the seller
+  event_log: Option<EventLog>,
+  stats: MarketStats
 }
 
-//Called on a new thread
-pub fn start_market(market_id: usize, market_tx: Sender<MarketMessages>, market_rx: Receiver<MarketMessages>, max_stock_id: usize) {
+//Called on a new thread, returns what was counted while the market was open.
+pub fn start_market(market_id: usize, market_tx: Sender<MarketMessages>, market_rx: Receiver<MarketMessages>, max_stock_id: usize, fee_bps: usize, history: SharedHistory, event_log: Option<EventLog>) -> MarketStats {
   //Create Market struct
-  let initial_history = Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]});
   let mut market = Market {id:market_id,
                              tellers: HashMap::new(),
                              actors: HashMap::new(),
                              active_transactions: vec![],
                              committed_actors: vec![],
                              pending_transactions: vec![],
-                             history: Arc::new(initial_history)};
-  //TODO: Initialize Tellers
-  {
-    let mut h = market.history.lock().unwrap();
+                             match_details: HashMap::new(),
+                             unanswered_requests: HashMap::new(),
+                             history,
+                             open: true,
+                             teller_shutdown: Arc::new(AtomicBool::new(false)),
+                             current_time: 0,
+                             fee_bps,
+                             event_log,
+                             stats: MarketStats::default()};
+  let mut teller_handles = vec![];
+  market.history.update(|h| {
     for i in 0..max_stock_id {
       let (tx, rx): (Sender<TellerMessages>, Receiver<TellerMessages>) = channel();
       market.tellers.insert(i, tx);
       let market_tx_clone = market_tx.clone();
-      Thread::spawn(move ||
-        {start_teller(i, market_tx_clone, rx);});
-      h.stocks.push(i);
+      let shutdown = market.teller_shutdown.clone();
+      teller_handles.push((i, thread::spawn(move ||
+        {start_teller(i, market_tx_clone, rx, shutdown);})));
+      //A history loaded from disk may already know the stock.
+      if !h.stocks.contains(&i) {
+        h.stocks.push(i);
+      }
     }
-  }
+  });
+  //Only the tellers and actors keep the market alive from here on.
+  drop(market_tx);
+  log_event(&mut market, Event::Open {stock_count: max_stock_id, fee_bps});
 
-  //Start the receive loop
-  loop {
-    let message = market_rx.recv().unwrap();
+  //Start the receive loop, it ends once every sender has hung up.
+  while let Ok(message) = market_rx.recv() {
     match message {
-      SellRequest(request) => {route(false, request, &market)},
-      BuyRequest(request) => {route(true, request, &market)},
+      SellRequest(request) => {
+        if market.open {
+          log_event(&mut market, Event::SellOrder {request: request.clone()});
+          market.stats.now(request.stock_id).sell_orders += 1;
+        }
+        route(false, request, &mut market)},
+      BuyRequest(request) => {
+        if market.open {
+          log_event(&mut market, Event::BuyOrder {request: request.clone()});
+          market.stats.now(request.stock_id).buy_orders += 1;
+        }
+        route(true, request, &mut market)},
       Commit(actor_id) => {
+        if !is_current_answer(&mut market, actor_id) {
+          continue;
+        }
         if has_active_transaction(&market, actor_id) {
+          log_event(&mut market, Event::Commit {actor_id});
           market.committed_actors.push(actor_id);
           let tup = get_active_transaction_involving(&market, actor_id);
           if contains(&market.committed_actors, tup.0.actor_id) && contains(&market.committed_actors, tup.1.actor_id) {
@@ -60,24 +97,36 @@ pub fn start_market(market_id: usize, market_tx: Sender<MarketMessages>, market_
             remove(&mut market.committed_actors, tup.0.actor_id);
             remove(&mut market.committed_actors, tup.1.actor_id);
 
+            //Ticks are too coarse to tell a settlement's delay apart, so it is measured on the wall clock.
+            let (matched_at, aggressor) = market.match_details.get(&tup.0.actor_id).cloned().unwrap_or((Instant::now(), Side::Buy));
+            let settlement_latency = matched_at.elapsed().as_micros() as usize;
+            //The seller is paid the price less the fee.
+            let fee = tup.0.price * market.fee_bps / 10_000;
+            let proceeds = TransactionRequest {price: tup.0.price - fee, ..tup.0.clone()};
             route_actor_message(&market, tup.0.actor_id, CommitTransaction(tup.1.clone()));
-            route_actor_message(&market, tup.1.actor_id, CommitTransaction(tup.0.clone()));
+            route_actor_message(&market, tup.1.actor_id, CommitTransaction(proceeds));
             remove_active_transaction(&mut market, &tup);
             move_pending_to_active(&mut market, tup.0.actor_id, tup.1.actor_id);
 
             println!("Market {} commited a transaction, stock {} was sold for {} with quantity {}", market.id, tup.0.stock_id, tup.0.price, tup.0.quantity);
-            let stock_id = tup.0.stock_id;
-            let mut h = market.history.lock().unwrap();
-            match h.history.entry(stock_id) {
-              Entry::Occupied(mut transaction) => {transaction.get_mut().push(tup);},
-              Entry::Vacant(val) => {val.insert(vec![tup]);}
-            }
+            log_event(&mut market, Event::Settled {buyer: tup.0.clone(), seller: tup.1.clone()});
+            let traded = market.stats.now(tup.0.stock_id);
+            traded.trades += 1;
+            traded.volume += tup.0.quantity;
+            let (market_id, time) = (market.id, market.current_time);
+            market.history.update(|h| h.record_trade(market_id, time, tup, aggressor, settlement_latency, fee));
           }
         }
       }
       Cancel(actor_id) => {
+        if !is_current_answer(&mut market, actor_id) {
+          continue;
+        }
         if has_active_transaction(&market, actor_id) {
+          log_event(&mut market, Event::Cancel {actor_id});
           let tup = get_active_transaction_involving(&market, actor_id);
+          log_event(&mut market, Event::Aborted {buyer: tup.0.clone(), seller: tup.1.clone()});
+          market.stats.now(tup.0.stock_id).aborts += 1;
           route_actor_message(&market, tup.0.actor_id, AbortTransaction);
           route_actor_message(&market, tup.1.actor_id, AbortTransaction);
 
@@ -93,101 +142,172 @@ pub fn start_market(market_id: usize, market_tx: Sender<MarketMessages>, market_
       RegisterActor(actor_id, actor_tx) => {
         let temp_clone = actor_tx.clone();
         market.actors.insert(actor_id, temp_clone);
-        actor_tx.send(History(market.history.clone())).unwrap();},
-      MatchRequest(buyer, seller) => {
+        let _ = actor_tx.send(History(market.history.clone()));},
+      MatchRequest(buyer, seller, aggressor) => {
+        if !market.open {
+          //The teller matched these before it was told to shut down.
+          continue;
+        }
+        if !market.actors.contains_key(&buyer.actor_id) || !market.actors.contains_key(&seller.actor_id) {
+          //One side crashed after its request reached the teller.
+          continue;
+        }
+        log_event(&mut market, Event::Match {buyer: buyer.clone(), seller: seller.clone()});
+        market.stats.now(buyer.stock_id).matches += 1;
         if has_active_transaction(&market, buyer.actor_id) || has_active_transaction(&market, seller.actor_id) {
           //add to pending transactions
-          market.pending_transactions.push((buyer, seller));
+          market.pending_transactions.push((buyer, seller, Instant::now(), aggressor));
         }
         else {
-          activate_transactions(&mut market, buyer, seller);
+          activate_transactions(&mut market, buyer, seller, Instant::now(), aggressor);
         }
       },
       RevokeRequest(stock_id, actor_id, transaction_id) => {
-        match market.tellers.get(&stock_id) {
-          Some(teller_rx) => {
-            teller_rx.send(TellerMessages::RevokeRequest(actor_id, transaction_id)).unwrap();
-          },
-          None => {}
+        if send_to_teller(&mut market, stock_id, TellerMessages::RevokeRequest(actor_id, transaction_id)).is_ok() {
+          log_event(&mut market, Event::Revoke {stock_id, actor_id, transaction_id});
+          if market.open {
+            market.stats.now(stock_id).revokes += 1;
+          }
         }
       },
       RequestActivityCount(actor_id, stock_id, buying) => {
         //look up the actor transmitter.
-        match market.actors.get(&actor_id) {
-          Some(channel) => {
-            let chan_clone = channel.clone();
-            route_teller(RequestCount(chan_clone, buying), &market, stock_id);
-            },
-          None => {}
+        if let Some(channel) = market.actors.get(&actor_id) {
+          let chan_clone = channel.clone();
+          route_teller(RequestCount(chan_clone, buying), &mut market, stock_id);
         }
+      },
+      ActorCrashed(actor_id) => {
+        remove_crashed_actor(&mut market, actor_id);
+      },
+      Time(current, max) => {
+        market.current_time = current;
+        market.stats.current_time = current;
+        log_event(&mut market, Event::Tick {max_time: max});
+        if market.open {
+          for (_, teller_tx) in market.tellers.iter() {
+            let _ = teller_tx.send(TellerMessages::RequestQuote(current));
+          }
+        }
+      },
+      Quote(stock_id, time, best_bid, best_ask) => {
+        market.stats.quote(stock_id, time, best_bid, best_ask);
+      },
+      Shutdown(main_channel) => {
+        close_market(&mut market);
+        main_channel.send(market.id).unwrap();
       }
     }
   }
+
+  if let Some(ref mut log) = market.event_log {log.flush();}
+
+  for (teller_id, handle) in teller_handles {
+    if handle.join().is_err() {
+      println!("ERROR: Teller {} of market {} panicked", teller_id, market.id);
+    }
+  }
+  market.stats
+}
+
+//Forgets a crashed actor: its open requests are revoked and its settlements aborted.
+fn remove_crashed_actor(market: &mut Market, actor_id: usize) {
+  log_event(market, Event::Crashed {actor_id});
+  market.actors.remove(&actor_id);
+  market.unanswered_requests.remove(&actor_id);
+  let stock_ids: Vec<usize> = market.tellers.keys().cloned().collect();
+  for stock_id in stock_ids {
+    let _ = send_to_teller(market, stock_id, TellerMessages::RevokeAll(actor_id));
+  }
+  market.pending_transactions.retain(|tup| tup.0.actor_id != actor_id && tup.1.actor_id != actor_id);
+
+  if has_active_transaction(market, actor_id) {
+    let tup = get_active_transaction_involving(market, actor_id);
+    log_event(market, Event::Aborted {buyer: tup.0.clone(), seller: tup.1.clone()});
+    //The crashed actor is no longer registered, only its counterpart hears about the abort.
+    route_actor_message(market, tup.0.actor_id, AbortTransaction);
+    route_actor_message(market, tup.1.actor_id, AbortTransaction);
+
+    remove(&mut market.committed_actors, tup.0.actor_id);
+    remove(&mut market.committed_actors, tup.1.actor_id);
+    remove_active_transaction(market, &tup);
+    move_pending_to_active(market, tup.0.actor_id, tup.1.actor_id);
+  }
+  println!("Market {} removed crashed actor {}", market.id, actor_id);
 }
 
-fn activate_transactions(market: &mut Market, mut buyer: TransactionRequest, mut seller: TransactionRequest) {
+//Stops trading: tellers drop their books and every unsettled transaction is rolled back.
+fn close_market(market: &mut Market) {
+  market.teller_shutdown.store(true, Ordering::SeqCst);
+  //A teller that sees the flag on a queued message stops before this reaches it.
+  for (_, teller_tx) in market.tellers.iter() {
+    let _ = teller_tx.send(TellerMessages::Shutdown);
+  }
+  //With no tellers left new buy, sell and revoke requests are silently dropped.
+  market.tellers.clear();
+
+  for tup in market.active_transactions.clone() {
+    route_actor_message(market, tup.0.actor_id, AbortTransaction);
+    route_actor_message(market, tup.1.actor_id, AbortTransaction);
+    log_event(market, Event::Aborted {buyer: tup.0, seller: tup.1});
+  }
+  println!("Market {} closed, aborted {} settlements and dropped {} pending matches",
+    market.id, market.active_transactions.len(), market.pending_transactions.len());
+  market.active_transactions.clear();
+  market.pending_transactions.clear();
+  market.match_details.clear();
+  market.committed_actors.clear();
+  market.open = false;
+  log_event(market, Event::Close);
+}
+
+fn activate_transactions(market: &mut Market, mut buyer: TransactionRequest, mut seller: TransactionRequest, matched_at: Instant, aggressor: Side) {
   //add to active transactions and notify both.
   buyer.price = seller.price;
   let smaller_quantity = cmp::min(seller.quantity, buyer.quantity);
   buyer.quantity = smaller_quantity;
   seller.quantity = smaller_quantity;
   let amount_to_pay = buyer.price;
-  let buyer_request = MoneyRequest {market_id: market.id, amount: amount_to_pay};
-  let seller_request = StockRequest {market_id: market.id, stock_id: seller.stock_id, quantity: seller.quantity};
+  let buyer_request = MoneyRequest {market_id: market.id, amount: amount_to_pay, transaction: (buyer.clone(), seller.clone())};
+  let seller_request = StockRequest {market_id: market.id, stock_id: seller.stock_id, quantity: seller.quantity, transaction: (buyer.clone(), seller.clone())};
 
-  route_actor_message(&market, buyer.actor_id, ActorMessages::MoneyRequest(buyer_request));
-  route_actor_message(&market, seller.actor_id, ActorMessages::StockRequest(seller_request));
+  log_event(market, Event::MoneyRequest {actor_id: buyer.actor_id, amount: amount_to_pay});
+  log_event(market, Event::StockRequest {actor_id: seller.actor_id, stock_id: seller.stock_id, quantity: seller.quantity});
+  route_actor_message(market, buyer.actor_id, ActorMessages::MoneyRequest(buyer_request));
+  route_actor_message(market, seller.actor_id, ActorMessages::StockRequest(seller_request));
+  *market.unanswered_requests.entry(buyer.actor_id).or_insert(0) += 1;
+  *market.unanswered_requests.entry(seller.actor_id).or_insert(0) += 1;
+  market.match_details.insert(buyer.actor_id, (matched_at, aggressor));
   market.active_transactions.push((buyer, seller));
 }
 
-fn move_pending_to_active(market: &mut Market, actor1: usize, actor2: usize) {
-  //actor 1
-  let mut actor_zero_in_transaction = true;
-  let mut actor_one_in_transaction = true;
-
-  for i in 0..market.pending_transactions.len() {
-    let pending_transaction = market.pending_transactions[i].clone();
-    if pending_transaction.0.actor_id == actor1 {
-      if !has_active_transaction(&market, pending_transaction.1.actor_id) {
-        // activate_transactions(market, pending_transaction.0.clone(), pending_transaction.1.clone());
-        // remove(&mut market.pending_transactions, pending_transaction);
-        // break;
-        actor_one_in_transaction = false;
-      }
-    }
-    if pending_transaction.1.actor_id == actor1 {
-      if !has_active_transaction(&market, pending_transaction.0.actor_id) {
-        // activate_transactions(market, pending_transaction.0.clone(), pending_transaction.1.clone());
-        // remove(&mut market.pending_transactions, pending_transaction);
-        // break;
-        actor_zero_in_transaction = false;
-      }
-    }
+//Actors answer every money and stock request in order, but Commit and Cancel don't say which
+//transaction they are for. An answer that still leaves requests unanswered belongs to a transaction
+//that was already aborted and must not be counted towards the actor's current one.
+fn is_current_answer(market: &mut Market, actor_id: usize) -> bool {
+  match market.unanswered_requests.get_mut(&actor_id) {
+    Some(count) if *count > 0 => {
+      *count -= 1;
+      *count == 0
+    },
+    _ => false
   }
+}
 
-  //actor 2
-  for i in 0..market.pending_transactions.len() {
+//Activates the oldest pending matches of actor1 and actor2 whose counterparts are free as well.
+fn move_pending_to_active(market: &mut Market, actor1: usize, actor2: usize) {
+  let mut i = 0;
+  while i < market.pending_transactions.len() {
     let pending_transaction = market.pending_transactions[i].clone();
-    if pending_transaction.0.actor_id == actor2 {
-      if !has_active_transaction(&market, pending_transaction.1.actor_id) {
-        // activate_transactions(market, pending_transaction.0.clone(), pending_transaction.1.clone());
-        // remove(&mut market.pending_transactions, pending_transaction);
-        // break;
-        actor_one_in_transaction = false;
-      }
+    let buyer_id = pending_transaction.0.actor_id;
+    let seller_id = pending_transaction.1.actor_id;
+    let involved = buyer_id == actor1 || buyer_id == actor2 || seller_id == actor1 || seller_id == actor2;
+    if involved && !has_active_transaction(market, buyer_id) && !has_active_transaction(market, seller_id) {
+      market.pending_transactions.remove(i);
+      activate_transactions(market, pending_transaction.0, pending_transaction.1, pending_transaction.2, pending_transaction.3);
     }
-    if pending_transaction.1.actor_id == actor2 {
-      if !has_active_transaction(&market, pending_transaction.0.actor_id) {
-        // activate_transactions(market, pending_transaction.0.clone(), pending_transaction.1.clone());
-        // remove(&mut market.pending_transactions, pending_transaction);
-        // break;
-        actor_zero_in_transaction = false;
-        if !actor_zero_in_transaction && !actor_one_in_transaction {
-          activate_transactions(market, pending_transaction.0.clone(), pending_transaction.1.clone());
-          remove(&mut market.pending_transactions, pending_transaction);
-          break;
-        }
-      }
+    else {
+      i += 1;
     }
   }
 }
@@ -201,7 +321,7 @@ fn remove<T:PartialEq>(vec: &mut Vec<T>, element: T) {
   }
 }
 
-fn contains<T:PartialEq>(vec: &Vec<T>, element: T) -> bool {
+fn contains<T:PartialEq>(vec: &[T], element: T) -> bool {
   for test_element in vec.iter() {
     if element == *test_element {
       return true;
@@ -211,6 +331,7 @@ fn contains<T:PartialEq>(vec: &Vec<T>, element: T) -> bool {
 }
 
 fn remove_active_transaction(market: &mut Market, tup: &(TransactionRequest, TransactionRequest)) {
+  market.match_details.remove(&tup.0.actor_id);
   for i in 0..market.active_transactions.len() {
     let test_active = market.active_transactions[i].clone();
     if  test_active.0 == tup.0 && test_active.1 == tup.1 {
@@ -238,32 +359,121 @@ fn has_active_transaction(market_ref : &Market, id: usize) -> bool {
   false
 }
 
+fn log_event(market: &mut Market, event: Event) {
+  let time = market.current_time;
+  let market_id = market.id;
+  if let Some(ref mut log) = market.event_log {log.record(time, market_id, event);}
+}
+
 fn route_actor_message(market: & Market, actor_id: usize, message: ActorMessages) {
-  match market.actors.get(&actor_id) {
-    Some(channel) => {channel.send(message).unwrap();},
-    None => {}
+  //An actor that has stopped or crashed has nothing left to settle.
+  if let Some(channel) = market.actors.get(&actor_id) {
+    let _ = channel.send(message);
   }
 }
 
-fn route(buying: bool, transaction: TransactionRequest, market: & Market) {
-  let tx;
-  match market.tellers.get(&transaction.stock_id) {
-    Some(channel) => {tx = channel;},
-    None => {return;}
-  }
-  if buying {
-    tx.send(TellerMessages::BuyRequest(transaction)).unwrap();
+fn route(buying: bool, transaction: TransactionRequest, market: &mut Market) {
+  let stock_id = transaction.stock_id;
+  let message = if buying {TellerMessages::BuyRequest(transaction)} else {TellerMessages::SellRequest(transaction)};
+  let _ = send_to_teller(market, stock_id, message);
+}
+
+fn route_teller(message: TellerMessages, market: &mut Market, teller_id: usize) {
+  //An actor asking a stopped teller for its book hears that it is empty instead of waiting forever.
+  if let Err(RequestCount(actor_tx, buying)) = send_to_teller(market, teller_id, message) {
+    let _ = actor_tx.send(ActorMessages::ReceiveActivityCount(teller_id, buying, 0));
   }
-  else {
-    tx.send(TellerMessages::SellRequest(transaction)).unwrap();
+}
+
+//A teller whose thread died is forgotten along with the message, like the tellers of a closed market.
+//Gives the message back if it was not delivered.
+fn send_to_teller(market: &mut Market, stock_id: usize, message: TellerMessages) -> Result<(), TellerMessages> {
+  let tx = match market.tellers.get(&stock_id) {
+    Some(channel) => channel,
+    None => {return Err(message);}
   };
+  match tx.send(message) {
+    Ok(()) => Ok(()),
+    Err(error) => {
+      println!("ERROR: Teller {} of market {} has stopped, its request was dropped", stock_id, market.id);
+      market.tellers.remove(&stock_id);
+      Err(error.0)
+    }
+  }
 }
 
-fn route_teller(message: TellerMessages, market: &Market, teller_id: usize) {
-  let tx;
-  match market.tellers.get(&teller_id) {
-    Some(channel) => {tx = channel;},
-    None => {return;}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn market_with_stopped_teller() -> Market {
+    let (teller_tx, teller_rx) = channel();
+    drop(teller_rx);
+    let mut market = Market {id: 0,
+                             tellers: HashMap::new(),
+                             actors: HashMap::new(),
+                             active_transactions: vec![],
+                             pending_transactions: vec![],
+                             match_details: HashMap::new(),
+                             committed_actors: vec![],
+                             unanswered_requests: HashMap::new(),
+                             history: SharedHistory::default(),
+                             open: true,
+                             teller_shutdown: Arc::new(AtomicBool::new(false)),
+                             current_time: 0,
+                             fee_bps: 0,
+                             event_log: None,
+                             stats: MarketStats::default()};
+    market.tellers.insert(0, teller_tx);
+    market
+  }
+
+  #[test]
+  fn stopped_teller_drops_requests_without_panicking() {
+    let mut market = market_with_stopped_teller();
+    route(true, TransactionRequest {transaction_id: 0, actor_id: 1, stock_id: 0, price: 10, quantity: 1}, &mut market);
+    assert!(market.tellers.is_empty());
+
+    let mut market = market_with_stopped_teller();
+    remove_crashed_actor(&mut market, 1);
+    assert!(market.tellers.is_empty());
+
+    let mut market = market_with_stopped_teller();
+    let (actor_tx, actor_rx) = channel();
+    route_teller(RequestCount(actor_tx, true), &mut market, 0);
+    match actor_rx.try_recv() {
+      Ok(ActorMessages::ReceiveActivityCount(0, true, 0)) => {},
+      _ => panic!("the actor was not told the book is empty")
+    }
+  }
+
+  #[test]
+  fn delayed_commit_has_a_settlement_latency() {
+    let (market_tx, market_rx) = channel();
+    let history = SharedHistory::default();
+    let market_history = history.clone();
+    let tx = market_tx.clone();
+    let market = thread::spawn(move || {start_market(0, tx, market_rx, 1, 0, market_history, None);});
+    let (buyer_tx, buyer_rx) = channel();
+    let (seller_tx, seller_rx) = channel();
+    market_tx.send(RegisterActor(1, buyer_tx)).unwrap();
+    market_tx.send(RegisterActor(2, seller_tx)).unwrap();
+    market_tx.send(BuyRequest(TransactionRequest {transaction_id: 0, actor_id: 1, stock_id: 0, price: 10, quantity: 1})).unwrap();
+    market_tx.send(SellRequest(TransactionRequest {transaction_id: 0, actor_id: 2, stock_id: 0, price: 10, quantity: 1})).unwrap();
+    //Both sides wait a while before they answer their settlement requests.
+    for (actor_id, actor_rx) in [(1, &buyer_rx), (2, &seller_rx)] {
+      while !matches!(actor_rx.recv().unwrap(), ActorMessages::MoneyRequest(_) | ActorMessages::StockRequest(_)) {}
+      thread::sleep(std::time::Duration::from_millis(5));
+      market_tx.send(Commit(actor_id)).unwrap();
+    }
+    while !matches!(buyer_rx.recv().unwrap(), CommitTransaction(_)) {}
+    let (reply_tx, reply_rx) = channel();
+    market_tx.send(Shutdown(reply_tx)).unwrap();
+    reply_rx.recv().unwrap();
+    drop(market_tx);
+    drop((buyer_rx, seller_rx));
+    market.join().unwrap();
+    let latency = history.snapshot().trades(0).last().unwrap().settlement_latency;
+    assert!(latency >= 10_000, "latency of {} µs", latency);
   }
-  tx.send(message).unwrap();
 }
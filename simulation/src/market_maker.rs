@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::stats::mean;
+use crate::trading::RestingOrders;
+
+/*
+A market maker. It keeps a bid below and an ask above the recent price of every stock, so
+there is always someone to trade with on both sides. The recent price is the mean unit price of
+the last window trades, a single lot sold for next to nothing doesn't drag the quotes down with
+it. A stock that hasn't traded yet is quoted around the close of its seeded price data, or around
+reference_price without any, so there is liquidity from the first tick on.
+
+Quotes are for one share each, a few levels deep, so a fill never costs more per share than
+quoted (a request's price covers its whole quantity). The more stock it holds the lower it
+quotes, which makes it likelier to sell than to buy more. It stops bidding on a stock once it
+holds max_inventory of it and can only offer what it holds. Its quotes for a stock rest as one
+RestingOrders quote (see trading.rs): a fill takes back the rest of them, and whenever the
+recent price or its inventory moves the quote it is replaced. Its bids over all
+stocks together never cost more than it could still lose before reaching max_loss, and if its
+net worth falls by more than max_loss anyway it revokes everything and stops.
+*/
+
+#[derive(Clone)]
+pub struct MarketMakerConfig {
+  pub window: usize, //Trades the recent price is the mean of, more of them smooth out odd lots but follow the price more slowly
+  pub reference_price: f64, //Price per share quoted around before a stock has any price
+  pub half_spread: f64, //Distance of the best bid and ask from the center, as a fraction of the recent price
+  pub skew: f64, //How far the center moves down at max_inventory, as a fraction of the recent price
+  pub levels: usize, //Quotes per side, each one unit further out
+  pub max_inventory: usize,
+  pub max_loss: f64 //Fraction of the starting net worth
+}
+
+impl Default for MarketMakerConfig {
+  fn default() -> MarketMakerConfig {
+    MarketMakerConfig {window: 10, reference_price: 50.0, half_spread: 0.05, skew: 0.1, levels: 3, max_inventory: 20, max_loss: 0.5}
+  }
+}
+
+struct MarketMaker {
+  config: MarketMakerConfig,
+  orders: RestingOrders,
+  inventories: HashMap<usize, usize>, //stock_id, what it held when it last quoted the stock
+  start_worth: Option<f64>,
+  halted: bool
+}
+
+pub fn start_market_maker(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, config: MarketMakerConfig, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Market Maker {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut maker = MarketMaker {config, orders: RestingOrders::default(), inventories: HashMap::new(), start_worth: None, halted: false};
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        maker.orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(_, _)) => {quote(&actor, &mut maker);},
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Market Maker) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {
+        //A fill changes the inventory, quote again right away instead of waiting for the next tick.
+        let filled = maker.inventories.iter().any(|(stock_id, inventory)| *inventory != *actor.stocks.get(stock_id).unwrap_or(&0));
+        if filled {
+          quote(&actor, &mut maker);
+        }
+        thread::sleep(Duration::from_millis(1));
+      },
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Market Maker {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+fn quote(actor: &Actor, maker: &mut MarketMaker) {
+  //Money or stock set aside for a settlement would make the inventory look smaller than it is.
+  if maker.halted || has_pending_transaction(actor) {
+    return;
+  }
+  let history = actor.history.snapshot();
+  let window = maker.config.window.max(1);
+  let marks: HashMap<usize, f64> = history.stocks.iter().map(|stock_id| {
+    let prices = history.recent_unit_prices(*stock_id, window);
+    let mark = if prices.is_empty() {history.last_unit_price(*stock_id).unwrap_or(maker.config.reference_price)} else {mean(&prices)};
+    (*stock_id, mark)
+  }).collect();
+
+  let worth = actor.money as f64 + actor.stocks.iter().map(|(stock_id, quantity)| *quantity as f64 * marks.get(stock_id).cloned().unwrap_or(0.0)).sum::<f64>();
+  let start_worth = *maker.start_worth.get_or_insert(worth);
+  let floor = start_worth * (1.0 - maker.config.max_loss);
+  if worth < floor {
+    println!("Market Maker {} lost more than {}% and stopped quoting", actor.id, maker.config.max_loss * 100.0);
+    maker.orders.revoke_all(actor);
+    maker.halted = true;
+    return;
+  }
+
+  for (stock_id, mark) in marks.iter() {
+    let inventory = *actor.stocks.get(stock_id).unwrap_or(&0);
+    maker.inventories.insert(*stock_id, inventory);
+    //Bids resting on other stocks already use part of what it can still lose.
+    let resting: usize = marks.keys().filter(|other| *other != stock_id).filter_map(|other| maker.orders.resting_bid(*other)).sum();
+    let budget = ((worth - floor).max(0.0) as usize).saturating_sub(resting).min(actor.money.saturating_sub(resting));
+
+    let config = &maker.config;
+    let position = inventory.min(config.max_inventory) as f64 / config.max_inventory.max(1) as f64;
+    let center = mark * (1.0 - config.skew * position);
+    let best_bid = ((center * (1.0 - config.half_spread)).floor() as usize).max(1);
+    let best_ask = ((center * (1.0 + config.half_spread)).ceil() as usize).max(best_bid + 1);
+
+    let mut bids = vec![];
+    let mut committed = 0;
+    //Prices are whole units, a bid rounded up above the last price would only overpay.
+    if inventory < config.max_inventory && best_bid as f64 <= *mark {
+      for level in 0..config.levels {
+        let price = best_bid.saturating_sub(level);
+        if price == 0 || committed + price > budget {
+          break;
+        }
+        committed += price;
+        bids.push(price);
+      }
+    }
+    let asks = (0..config.levels.min(inventory)).map(|level| best_ask + level).collect();
+    maker.orders.quote(actor, *stock_id, bids, asks);
+  }
+}
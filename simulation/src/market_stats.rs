@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use crate::stats::{mean, stddev};
+use crate::messages::{MarketHistory, Trade};
+use crate::portfolio::run_trades;
+
+/*
+Counts what happens in a market, per tick and stock, while it is open.
+Orders are the buy and sell requests it routes, revokes are orders taken back before they
+matched, aborts are settlements that ended with a Cancel instead of both sides committing.
+Every tick the tellers report their best bid and ask per share, a request's price divided by
+its quantity. The spread is the best ask per share minus the best bid per share, for the ticks
+that had both. The tellers match on whole request prices, so a bid for one share can sit above
+an ask for several without the two matching, and the spread of such a crossed book is negative.
+*/
+
+#[derive(Clone, Debug, Default)]
+pub struct TickStats {
+  pub buy_orders: usize,
+  pub sell_orders: usize,
+  pub revokes: usize,
+  pub matches: usize,
+  pub aborts: usize,
+  pub trades: usize,
+  pub volume: usize,
+  pub best_bid: Option<f64>,
+  pub best_ask: Option<f64>
+}
+
+impl TickStats {
+  //None when either side of the book was empty.
+  pub fn spread(&self) -> Option<f64> {
+    match (self.best_bid, self.best_ask) {
+      (Some(bid), Some(ask)) => Some(ask - bid),
+      _ => None
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MarketStats {
+  pub current_time: usize,
+  pub ticks: BTreeMap<(usize, usize), TickStats> //(tick time, stock_id)
+}
+
+impl MarketStats {
+  //The counters of the tick that is running now.
+  pub fn now(&mut self, stock_id: usize) -> &mut TickStats {
+    self.ticks.entry((self.current_time, stock_id)).or_default()
+  }
+
+  pub fn quote(&mut self, stock_id: usize, time: usize, best_bid: Option<f64>, best_ask: Option<f64>) {
+    let tick = self.ticks.entry((time, stock_id)).or_default();
+    tick.best_bid = best_bid;
+    tick.best_ask = best_ask;
+  }
+}
+
+//One stock over the whole run.
+pub struct StockSummary {
+  pub stock_id: usize,
+  pub orders: usize,
+  pub revokes: usize,
+  pub matches: usize,
+  pub aborts: usize,
+  pub trades: usize,
+  pub volume: usize,
+  pub mean_bid: Option<f64>, //Best bid per share, averaged over the ticks there was one
+  pub mean_ask: Option<f64>,
+  pub mean_spread: Option<f64>, //Averaged over the ticks with both a bid and an ask, negative if the book was mostly crossed
+  pub mean_latency: Option<f64>, //Microseconds from match to settlement
+  pub volatility: f64 //Standard deviation of trade to trade log returns of the unit price
+}
+
+impl StockSummary {
+  pub fn order_to_trade(&self) -> Option<f64> {
+    if self.trades == 0 {None} else {Some(self.orders as f64 / self.trades as f64)}
+  }
+
+  //Share of orders revoked, in percent.
+  pub fn cancel_rate(&self) -> f64 {
+    if self.orders == 0 {0.0} else {100.0 * self.revokes as f64 / self.orders as f64}
+  }
+
+  //Share of settlements that were aborted, in percent.
+  pub fn abort_rate(&self) -> f64 {
+    let settlements = self.trades + self.aborts;
+    if settlements == 0 {0.0} else {100.0 * self.aborts as f64 / settlements as f64}
+  }
+}
+
+//Per stock, latency and volatility come from the trades the run recorded.
+pub fn summarize(stats: &MarketStats, history: &MarketHistory) -> Vec<StockSummary> {
+  let trades = run_trades(history);
+  let mut stock_ids: Vec<usize> = stats.ticks.keys().map(|(_, stock_id)| *stock_id).collect();
+  stock_ids.extend(history.stocks.iter().cloned());
+  stock_ids.sort();
+  stock_ids.dedup();
+
+  stock_ids.into_iter().map(|stock_id| {
+    let ticks: Vec<&TickStats> = stats.ticks.iter().filter(|((_, id), _)| *id == stock_id).map(|(_, tick)| tick).collect();
+    let bids: Vec<f64> = ticks.iter().filter_map(|tick| tick.best_bid).collect();
+    let asks: Vec<f64> = ticks.iter().filter_map(|tick| tick.best_ask).collect();
+    let spreads: Vec<f64> = ticks.iter().filter_map(|tick| tick.spread()).collect();
+    let of_stock: Vec<&Trade> = trades.iter().filter(|trade| trade.buyer.stock_id == stock_id).cloned().collect();
+    let latencies: Vec<f64> = of_stock.iter().map(|trade| trade.settlement_latency as f64).collect();
+    StockSummary {stock_id,
+                  orders: ticks.iter().map(|tick| tick.buy_orders + tick.sell_orders).sum(),
+                  revokes: ticks.iter().map(|tick| tick.revokes).sum(),
+                  matches: ticks.iter().map(|tick| tick.matches).sum(),
+                  aborts: ticks.iter().map(|tick| tick.aborts).sum(),
+                  trades: ticks.iter().map(|tick| tick.trades).sum(),
+                  volume: ticks.iter().map(|tick| tick.volume).sum(),
+                  mean_bid: if bids.is_empty() {None} else {Some(mean(&bids))},
+                  mean_ask: if asks.is_empty() {None} else {Some(mean(&asks))},
+                  mean_spread: if spreads.is_empty() {None} else {Some(mean(&spreads))},
+                  mean_latency: if latencies.is_empty() {None} else {Some(mean(&latencies))},
+                  volatility: volatility(&unit_prices(&of_stock))}
+  }).collect()
+}
+
+//Unit prices of the trades in order, trades at price 0 have no meaningful return and are left out.
+pub fn unit_prices(trades: &[&Trade]) -> Vec<f64> {
+  trades.iter().filter_map(|trade| trade.unit_price()).collect()
+}
+
+//Standard deviation of the log returns from one price to the next.
+pub fn volatility(prices: &[f64]) -> f64 {
+  let returns: Vec<f64> = prices.windows(2).map(|pair| (pair[1] / pair[0]).ln()).collect();
+  stddev(&returns)
+}
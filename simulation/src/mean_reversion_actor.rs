@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::stats::{mean, stddev};
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::trading::RestingOrders;
+
+/*
+Bets on prices returning to their average. Over the log unit prices of a stock's last window
+trades it takes the mean and standard deviation, and scores the last price by how many
+deviations it is away from the mean. On log prices a lot sold at a tenth of the going price
+counts as far off as one sold at ten times it, rather than the rare high prints blowing up
+the deviation so that nothing below the mean ever scores. Below -buy_threshold it buys with
+position_fraction of its cash, above sell_threshold it sells all it holds of the stock,
+whatever it paid for it. Unlike the Smarter Actor it doesn't wait to sell above its purchase
+price.
+
+Orders are sized, priced and kept resting by trading::RestingOrders.
+*/
+
+#[derive(Clone)]
+pub struct MeanReversionConfig {
+  pub window: usize, //Trades the mean and deviation are taken over
+  pub buy_threshold: f64, //Standard deviations below the mean
+  pub sell_threshold: f64, //Standard deviations above the mean
+  pub position_fraction: f64, //Share of the cash put into one entry
+  pub max_positions: usize,
+  pub slippage: f64 //Fraction of the last price paid above it when buying, taken below it when selling
+}
+
+impl Default for MeanReversionConfig {
+  fn default() -> MeanReversionConfig {
+    MeanReversionConfig {window: 20, buy_threshold: 1.0, sell_threshold: 1.0, position_fraction: 0.5, max_positions: 2, slippage: 0.02}
+  }
+}
+
+pub fn start_mean_reversion_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, config: MeanReversionConfig, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Mean Reversion Actor {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut orders = RestingOrders::default();
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(_, _)) => {trade(&actor, &config, &mut orders);},
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Mean Reversion Actor) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Mean Reversion Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+//Standard deviations of the last log price from the mean of the last window, None while there are fewer than window prices or they are all the same.
+pub fn z_score(prices: &[f64], window: usize) -> Option<f64> {
+  if window < 2 || prices.len() < window || prices[prices.len() - window..].iter().any(|price| *price <= 0.0) {
+    return None;
+  }
+  let recent: Vec<f64> = prices[prices.len() - window..].iter().map(|price| price.ln()).collect();
+  let deviation = stddev(&recent);
+  if deviation > 0.0 {Some((recent[window - 1] - mean(&recent)) / deviation)} else {None}
+}
+
+fn trade(actor: &Actor, config: &MeanReversionConfig, orders: &mut RestingOrders) {
+  if has_pending_transaction(actor) {
+    return;
+  }
+  let history = actor.history.snapshot();
+  let mut positions = actor.stocks.values().filter(|quantity| **quantity > 0).count();
+  let mut cash = actor.money;
+
+  for stock_id in history.stocks.iter() {
+    let prices = history.recent_unit_prices(*stock_id, config.window);
+    let (score, mark) = match (z_score(&prices, config.window), prices.last()) {
+      (Some(score), Some(mark)) => (score, *mark),
+      _ => {
+        orders.revoke(actor, *stock_id);
+        continue;
+      }
+    };
+    let held = *actor.stocks.get(stock_id).unwrap_or(&0);
+
+    if held > 0 && score > config.sell_threshold {
+      orders.exit_offer(actor, *stock_id, mark, held, config.slippage);
+      continue;
+    }
+    else if held == 0 && score < -config.buy_threshold && positions < config.max_positions {
+      if let Some(committed) = orders.entry_bid(actor, *stock_id, mark, cash, config.position_fraction, config.slippage) {
+        cash = cash.saturating_sub(committed);
+        positions += 1;
+        continue;
+      }
+    }
+    orders.revoke(actor, *stock_id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn scores_log_prices() {
+    //ln 1, ln 10, ln 100 are evenly spaced: the last is one deviation above their mean.
+    assert!((z_score(&[1.0, 10.0, 100.0], 3).unwrap() - 1.0).abs() < 1e-9);
+    //A tenth and ten times the going price are equally far off.
+    let low = z_score(&[10.0, 10.0, 10.0, 20.0, 1.0], 5).unwrap();
+    let high = z_score(&[10.0, 10.0, 10.0, 5.0, 100.0], 5).unwrap();
+    assert!((low + high).abs() < 1e-9);
+  }
+
+  #[test]
+  fn no_score_without_a_spread_of_positive_prices() {
+    assert_eq!(z_score(&[5.0, 6.0], 3), None);
+    assert_eq!(z_score(&[7.0, 7.0, 7.0], 3), None);
+    assert_eq!(z_score(&[3.0, 0.0, 4.0], 3), None);
+    assert!(z_score(&[0.0, 3.0, 2.0, 4.0], 3).is_some());
+  }
+}
@@ -1,8 +1,15 @@
 use std::collections::HashMap;
 use std::sync::mpsc::{Sender};
 use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
+use crate::indicators::{IndicatorConfig, Indicators};
+use crate::append_log::{AppendLog, LogView};
+
 // Messages to a Market
 pub enum MarketMessages {
   RequestActivityCount(usize, usize, bool), //actor id, stock id, buying
@@ -11,8 +18,12 @@ pub enum MarketMessages {
   Commit(usize), //Id of the actor
   Cancel(usize), //Id of the actor
   RegisterActor(usize, Sender<ActorMessages>), //Actor's id, transmit channel
-  MatchRequest(TransactionRequest, TransactionRequest), // (Buyer's Request, Seller's Request)
-  RevokeRequest(usize, usize, usize) //stock_id, actor_id, transaction_id
+  MatchRequest(TransactionRequest, TransactionRequest, Side), // (Buyer's Request, Seller's Request, side whose request arrived last)
+  RevokeRequest(usize, usize, usize), //stock_id, actor_id, transaction_id
+  Shutdown(Sender<usize>), //Reply channel, receives the market id once the market is closed
+  ActorCrashed(usize), //Id of the actor whose strategy panicked
+  Time(usize, usize), //Current time, max time
+  Quote(usize, usize, Option<f64>, Option<f64>) //stock_id, time asked for, best bid and ask per share
 }
 
 // Messages from a Market to an Actor
@@ -22,7 +33,7 @@ pub enum ActorMessages {
   MoneyRequest(MoneyRequest), //The amount of money needed to buy the stock(s)
   CommitTransaction(TransactionRequest), //The information related to the transaction
   AbortTransaction,
-  History(Arc<Mutex<MarketHistory>>),
+  History(SharedHistory),
   Time(usize, usize), //Current time, max time
   Stop(Sender<(usize, String)>) //Actor id, status
 }
@@ -32,10 +43,13 @@ pub enum TellerMessages {
   RequestCount(Sender<ActorMessages>, bool), //actor transmitter, buying
   SellRequest(TransactionRequest),
   BuyRequest(TransactionRequest),
-  RevokeRequest(usize, usize) //actor_id, transaction_id (unique to a single actor)
+  RevokeRequest(usize, usize), //actor_id, transaction_id (unique to a single actor)
+  RevokeAll(usize), //actor_id, drops every open request of a crashed actor
+  RequestQuote(usize), //time, answered with the best bid and ask per share
+  Shutdown //Cancel all open requests and exit
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TransactionRequest {
   pub transaction_id: usize,
   pub actor_id: usize, //Id of Actor initiating the request
@@ -58,23 +72,124 @@ impl fmt::Debug for TransactionRequest {
   }
 }
 
+//Which side of a trade took liquidity: the request that arrived last and matched a resting one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+  Buy,
+  Sell
+}
 
 #[derive(Clone)]
 pub struct StockRequest {
   pub market_id: usize,
   pub stock_id: usize,
   pub quantity: usize,
+  pub transaction: (TransactionRequest, TransactionRequest) //(Buyer's, Seller's) request being settled
 }
 
 #[derive(Clone)]
 pub struct MoneyRequest {
   pub market_id: usize,
   pub amount: usize,
+  pub transaction: (TransactionRequest, TransactionRequest) //(Buyer's, Seller's) request being settled
 }
 
+#[derive(Clone)]
 pub struct MarketHistory {
-  pub history: HashMap<usize, Vec<(TransactionRequest, TransactionRequest)>>, // stock_id, <Buy,Sell>
-  pub stocks: Vec<usize>
+  pub history: HashMap<usize, AppendLog<Trade>>, // stock_id, trades oldest first
+  pub stocks: Vec<usize>,
+  pub next_seq: usize, //Sequence number of the next recorded trade, across all stocks
+  pub time_offset: usize, //Added to the run's clock when recording, places this run after a loaded history
+  pub candle_interval: usize, //Simulated time covered by one candle
+  pub candles: HashMap<usize, AppendLog<Candle>>, // stock_id, candles oldest first
+  pub indicator_config: IndicatorConfig,
+  pub indicators: HashMap<usize, Indicators>, // stock_id, updated on every trade
+  pub fundamentals: HashMap<usize, Arc<Vec<usize>>>, // stock_id, fundamental value for each step of the run, shared between snapshots
+  pub fundamental_step: usize //Simulated time each fundamental value holds for, the candle interval unless fundamental::generate set it
+}
+
+//A settled transaction as recorded by the market.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trade {
+  pub seq: usize,
+  pub time: usize, //Simulated time at settlement
+  pub market_id: usize,
+  pub buyer: TransactionRequest,
+  pub seller: TransactionRequest,
+  pub aggressor: Side,
+  pub settlement_latency: usize, //Microseconds of wall time between the match and the settlement
+  pub fee: usize //Kept by the market out of the seller's proceeds
+}
+
+impl Trade {
+  pub fn price(&self) -> usize {
+    self.buyer.price
+  }
+
+  pub fn quantity(&self) -> usize {
+    self.buyer.quantity
+  }
+
+  //The price covers the whole quantity. None for a trade at price 0, which says nothing about value.
+  pub fn unit_price(&self) -> Option<f64> {
+    if self.quantity() == 0 || self.price() == 0 {None} else {Some(self.price() as f64 / self.quantity() as f64)}
+  }
+}
+
+//Open, high, low and close price of one share and volume of one stock over one candle interval.
+//Intervals without any trade have no candle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+  pub start_time: usize,
+  pub open: usize,
+  pub high: usize,
+  pub low: usize,
+  pub close: usize,
+  pub volume: usize //Shares traded
+}
+
+pub const DEFAULT_CANDLE_INTERVAL: usize = 1000;
+
+/*
+The market's history as shared with the actors. Readers take a snapshot, an immutable
+MarketHistory they can hold onto and query for as long as they like; taking one never
+blocks and never waits for the market. The market applies each change to a copy of the
+current snapshot and publishes the copy. Trades and candles live in AppendLogs, so the copy
+shares everything but the last few entries with the snapshot it was made from.
+*/
+#[derive(Clone)]
+pub struct SharedHistory {
+  current: Arc<ArcSwap<MarketHistory>>,
+  writer: Arc<Mutex<()>> //Serializes updates, readers never take it
+}
+
+impl Default for SharedHistory {
+  fn default() -> SharedHistory {
+    SharedHistory::new(MarketHistory::default())
+  }
+}
+
+impl SharedHistory {
+  pub fn new(history: MarketHistory) -> SharedHistory {
+    SharedHistory {current: Arc::new(ArcSwap::from_pointee(history)),
+                   writer: Arc::new(Mutex::new(()))}
+  }
+
+  pub fn snapshot(&self) -> Arc<MarketHistory> {
+    self.current.load_full()
+  }
+
+  pub fn update<R, F: FnOnce(&mut MarketHistory) -> R>(&self, change: F) -> R {
+    //Nothing is published while the lock is held, so a panic under it leaves no half made change behind.
+    let _writer = match self.writer.lock() {
+      Ok(guard) => guard,
+      Err(poisoned) => poisoned.into_inner()
+    };
+    let mut next = MarketHistory::clone(&self.current.load());
+    let result = change(&mut next);
+    self.current.store(Arc::new(next));
+    result
+  }
 }
 
 impl fmt::Display for MarketHistory {
@@ -83,11 +198,157 @@ impl fmt::Display for MarketHistory {
   }
 }
 
+impl Default for MarketHistory {
+  fn default() -> MarketHistory {
+    MarketHistory::new(DEFAULT_CANDLE_INTERVAL)
+  }
+}
+
 impl MarketHistory {
+  pub fn new(candle_interval: usize) -> MarketHistory {
+    MarketHistory {history: HashMap::new(),
+                   stocks: vec![],
+                   next_seq: 0,
+                   time_offset: 0,
+                   candle_interval: candle_interval.max(1),
+                   candles: HashMap::new(),
+                   indicator_config: IndicatorConfig::default(),
+                   indicators: HashMap::new(),
+                   fundamentals: HashMap::new(),
+                   fundamental_step: candle_interval.max(1)}
+  }
+
+  //Called by the market for every committed transaction.
+  pub fn record_trade(&mut self, market_id: usize, time: usize, trade: (TransactionRequest, TransactionRequest), aggressor: Side, settlement_latency: usize, fee: usize) {
+    let stock_id = trade.0.stock_id;
+    let price = trade.0.price;
+    let quantity = trade.0.quantity;
+    let time = time + self.time_offset;
+    let record = Trade {seq: self.next_seq,
+                        time,
+                        market_id,
+                        buyer: trade.0,
+                        seller: trade.1,
+                        aggressor,
+                        settlement_latency,
+                        fee};
+    self.next_seq += 1;
+    //A trade at price 0 says nothing about value, the indicators leave it out like Trade::unit_price.
+    if let Some(unit_price) = record.unit_price() {
+      let config = &self.indicator_config;
+      self.indicators.entry(stock_id).or_insert_with(|| Indicators::new(config)).update(unit_price, quantity);
+    }
+    self.history.entry(stock_id).or_default().push(record);
+
+    //Candles are per share like seeded price data, rounded to the market's whole prices.
+    let price = (price as f64 / quantity.max(1) as f64).round() as usize;
+    let start_time = time - time % self.candle_interval;
+    let candles = self.candles.entry(stock_id).or_default();
+    match candles.last_mut() {
+      Some(candle) if candle.start_time == start_time => {
+        candle.high = candle.high.max(price);
+        candle.low = candle.low.min(price);
+        candle.close = price;
+        candle.volume += quantity;
+        return;
+      },
+      _ => {}
+    }
+    candles.push(Candle {start_time, open: price, high: price, low: price, close: price, volume: quantity});
+  }
+
+  //Adds a candle that did not come from a recorded trade, e.g. external price data seeded before the run.
+  pub fn record_candle(&mut self, stock_id: usize, candle: Candle) {
+    let config = &self.indicator_config;
+    self.indicators.entry(stock_id).or_insert_with(|| Indicators::new(config)).update(candle.close as f64, candle.volume);
+    self.candles.entry(stock_id).or_default().push(candle);
+  }
+
+  //The stock's fundamental value at the run's time now, the last value holds once the series runs out.
+  pub fn fundamental_value(&self, stock_id: usize, now: usize) -> Option<usize> {
+    match self.fundamentals.get(&stock_id) {
+      Some(values) if !values.is_empty() => Some(values[(now / self.fundamental_step).min(values.len() - 1)]),
+      _ => None
+    }
+  }
+
+  pub fn candles(&self, stock_id: usize) -> LogView<'_, Candle> {
+    match self.candles.get(&stock_id) {
+      Some(candles) => candles.view(),
+      None => LogView::empty()
+    }
+  }
+
+  //Candles starting in [from, to)
+  pub fn candles_between(&self, stock_id: usize, from: usize, to: usize) -> LogView<'_, Candle> {
+    match self.candles.get(&stock_id) {
+      Some(candles) => candles.range(candles.partition_point(|candle| candle.start_time < from),
+                                     candles.partition_point(|candle| candle.start_time < to)),
+      None => LogView::empty()
+    }
+  }
+
+  pub fn last_candle(&self, stock_id: usize) -> Option<Candle> {
+    self.candles(stock_id).last().cloned()
+  }
+
+  pub fn trades(&self, stock_id: usize) -> LogView<'_, Trade> {
+    match self.history.get(&stock_id) {
+      Some(trades) => trades.view(),
+      None => LogView::empty()
+    }
+  }
+
+  //Trades of one stock that settled in [from, to)
+  pub fn trades_between(&self, stock_id: usize, from: usize, to: usize) -> LogView<'_, Trade> {
+    match self.history.get(&stock_id) {
+      Some(trades) => trades.range(trades.partition_point(|trade| trade.time < from),
+                                   trades.partition_point(|trade| trade.time < to)),
+      None => LogView::empty()
+    }
+  }
+
+  //Trades of one stock in the window ending at now, e.g. the last 1000 ms.
+  //now is the run's clock as sent in Time messages, the window may reach back into a loaded history.
+  pub fn recent_trades(&self, stock_id: usize, now: usize, window: usize) -> LogView<'_, Trade> {
+    let now = now + self.time_offset;
+    self.trades_between(stock_id, now.saturating_sub(window), now + 1)
+  }
+
+  //Trades of every stock that settled in [from, to), in the order they were recorded.
+  pub fn all_trades_between(&self, from: usize, to: usize) -> Vec<&Trade> {
+    let mut trades: Vec<&Trade> = self.stocks.iter().flat_map(|stock_id| self.trades_between(*stock_id, from, to).iter()).collect();
+    trades.sort_by_key(|trade| trade.seq);
+    trades
+  }
+
+  //Price of one share in the last trade, or the last candle's close of a seeded history.
+  pub fn last_unit_price(&self, stock_id: usize) -> Option<f64> {
+    match self.trades(stock_id).iter().rev().find_map(|trade| trade.unit_price()) {
+      Some(price) => Some(price),
+      None => self.last_candle(stock_id).map(|candle| candle.close as f64)
+    }
+  }
+
+  //Prices of one share in up to the last count trades, oldest first.
+  pub fn recent_unit_prices(&self, stock_id: usize, count: usize) -> Vec<f64> {
+    let mut prices: Vec<f64> = self.trades(stock_id).iter().rev().filter_map(|trade| trade.unit_price()).take(count).collect();
+    prices.reverse();
+    prices
+  }
+
+  pub fn indicators(&self, stock_id: usize) -> Option<&Indicators> {
+    self.indicators.get(&stock_id)
+  }
+
   pub fn len(&self) -> usize {
     self.history.len()
   }
 
+  pub fn is_empty(&self) -> bool {
+    self.history.is_empty()
+  }
+
   pub fn transaction_count(&self, stock_id: usize) -> usize {
     match self.history.get(&stock_id) {
       Some(transactions) => transactions.len(),
@@ -98,8 +359,9 @@ impl MarketHistory {
   pub fn last_transaction_for_stock(&self, stock_id: usize) -> Option<(TransactionRequest, TransactionRequest)> {
     match self.history.get(&stock_id) {
       Some(transactions) => {
-        if transactions.len() > 0 {
-          Some(transactions[transactions.len() - 1].clone())
+        if !transactions.is_empty() {
+          let last = &transactions[transactions.len() - 1];
+          Some((last.buyer.clone(), last.seller.clone()))
         }
         else {
           None
@@ -109,45 +371,26 @@ impl MarketHistory {
     }
   }
 
+  //last_unit_price rounded to a whole price per share, what a one-share request would be priced at.
   pub fn last_sold_price(&self, stock_id: usize) -> Option<usize> {
-    match self.history.get(&stock_id) {
-      Some(transactions) => {
-        if transactions.len() > 0 {
-          Some(transactions[transactions.len() - 1].0.price)
-        }
-        else {
-          None
-        }
-      },
-      None => None
-    }
+    self.last_unit_price(stock_id).map(|price| price.round() as usize)
   }
 
   pub fn increasing(&self, stock_id: usize) -> bool {
     match self.history.get(&stock_id) {
-      Some(transactions) => {
-        if transactions.len() > 1 {
-          transactions[transactions.len() - 1].0.price > transactions[transactions.len() - 2].0.price
-        }
-        else {
-          false
-        }
+      Some(transactions) if transactions.len() > 1 => {
+        transactions[transactions.len() - 1].buyer.price > transactions[transactions.len() - 2].buyer.price
       },
-      None => false
+      _ => false
     }
   }
 
   pub fn decreasing(&self, stock_id: usize) -> bool {
     match self.history.get(&stock_id) {
-      Some(transactions) => {
-        if transactions.len() > 1 {
-          transactions[transactions.len() - 1].0.price < transactions[transactions.len() - 2].0.price
-        }
-        else {
-          false
-        }
+      Some(transactions) if transactions.len() > 1 => {
+        transactions[transactions.len() - 1].buyer.price < transactions[transactions.len() - 2].buyer.price
       },
-      None => false
+      _ => false
     }
   }
 
@@ -160,15 +403,46 @@ impl MarketHistory {
 
   pub fn can_purchase(&self, stock_id: usize, price: usize) -> bool {
     match self.history.get(&stock_id) {
-      Some(transactions) => {
-        if transactions.len() > 1 {
-          transactions[transactions.len() - 1].0.price <= price
-        }
-        else {
-          false
-        }
-      },
-      None => false
+      Some(transactions) if transactions.len() > 1 => transactions[transactions.len() - 1].buyer.price <= price,
+      _ => false
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(actor_id: usize, price: usize, quantity: usize) -> TransactionRequest {
+    TransactionRequest {transaction_id: 0, actor_id, stock_id: 0, price, quantity}
+  }
+
+  #[test]
+  fn candles_of_multi_share_trades_are_per_share() {
+    let mut history = MarketHistory::new(1000);
+    history.record_trade(0, 10, (request(1, 400, 10), request(2, 400, 10)), Side::Buy, 10, 0);
+    history.record_trade(0, 20, (request(1, 90, 2), request(2, 90, 2)), Side::Sell, 20, 0);
+    history.record_trade(0, 30, (request(1, 126, 3), request(2, 126, 3)), Side::Buy, 30, 0);
+    assert_eq!(history.last_candle(0), Some(Candle {start_time: 0, open: 40, high: 45, low: 40, close: 42, volume: 15}));
+  }
+
+  #[test]
+  fn last_sold_price_is_per_share_with_or_without_trades() {
+    let mut history = MarketHistory::new(1000);
+    history.record_candle(0, Candle {start_time: 0, open: 30, high: 35, low: 28, close: 33, volume: 100});
+    assert_eq!(history.last_sold_price(0), Some(33));
+    history.record_trade(0, 10, (request(1, 125, 3), request(2, 125, 3)), Side::Buy, 10, 0);
+    assert_eq!(history.last_sold_price(0), Some(42));
+    assert_eq!(history.last_sold_price(1), None);
+  }
+
+  #[test]
+  fn indicators_skip_trades_at_price_0() {
+    let mut history = MarketHistory::new(1000);
+    history.record_trade(0, 10, (request(1, 0, 5), request(2, 0, 5)), Side::Buy, 10, 0);
+    assert!(history.indicators(0).is_none());
+    history.record_trade(0, 20, (request(1, 30, 3), request(2, 30, 3)), Side::Buy, 20, 0);
+    history.record_trade(0, 30, (request(1, 0, 2), request(2, 0, 2)), Side::Sell, 30, 0);
+    assert_eq!(history.indicators(0).and_then(|indicators| indicators.vwap()), Some(10.0));
+  }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::stats::mean;
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::trading::{CostBasis, RestingOrders};
+
+/*
+A trend follower. Its signal is how far the mean unit price of the last short_window trades
+is above the mean of the last long_window trades. When it holds nothing of a stock and the
+signal is above entry_threshold it buys, spending position_fraction of its cash, in at most
+max_positions stocks at once. It sells everything it holds of a stock once the signal drops
+below exit_threshold, or the fast mean falls stop_loss below what it paid or rises take_profit
+above it.
+There is no short selling, so a falling trend only ever means getting out.
+
+Orders are priced slippage beyond the fast mean and kept resting by trading::RestingOrders.
+*/
+
+#[derive(Clone)]
+pub struct MomentumConfig {
+  pub short_window: usize, //Trades in the fast mean
+  pub long_window: usize, //Trades in the slow mean, no signal until a stock has traded this often
+  pub entry_threshold: f64, //Fast mean over slow mean minus one
+  pub exit_threshold: f64,
+  pub position_fraction: f64, //Share of the cash put into one entry
+  pub max_positions: usize,
+  pub stop_loss: f64, //Fraction of the entry price
+  pub take_profit: f64,
+  pub slippage: f64 //Fraction of the fast mean paid above it when buying, taken below it when selling
+}
+
+impl Default for MomentumConfig {
+  fn default() -> MomentumConfig {
+    MomentumConfig {short_window: 5, long_window: 20, entry_threshold: 0.02, exit_threshold: 0.0,
+                    position_fraction: 0.5, max_positions: 2, stop_loss: 0.1, take_profit: 0.25, slippage: 0.02}
+  }
+}
+
+pub fn start_momentum_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, config: MomentumConfig, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Momentum Actor {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut orders = RestingOrders::default();
+  let mut cost_basis = CostBasis::default();
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        cost_basis.record(&actor, &message);
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(_, _)) => {trade(&actor, &config, &mut orders, &cost_basis);},
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Momentum Actor) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Momentum Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+//Fast mean over slow mean minus one, None until the stock has long_window priced trades.
+pub fn trend_signal(prices: &[f64], short_window: usize, long_window: usize) -> Option<f64> {
+  if short_window == 0 || prices.len() < long_window.max(short_window) {
+    return None;
+  }
+  let slow = mean(&prices[prices.len() - long_window.max(1)..]);
+  let fast = mean(&prices[prices.len() - short_window..]);
+  if slow > 0.0 {Some(fast / slow - 1.0)} else {None}
+}
+
+fn trade(actor: &Actor, config: &MomentumConfig, orders: &mut RestingOrders, cost_basis: &CostBasis) {
+  //Stock set aside for a settlement isn't in actor.stocks, wait until it is back or gone.
+  if has_pending_transaction(actor) {
+    return;
+  }
+  let history = actor.history.snapshot();
+  let mut positions = actor.stocks.values().filter(|quantity| **quantity > 0).count();
+  let mut cash = actor.money;
+
+  for stock_id in history.stocks.iter() {
+    let prices = history.recent_unit_prices(*stock_id, config.long_window.max(config.short_window));
+    //A single lot sold for next to nothing makes the last unit price meaningless, the fast mean is steadier.
+    let (signal, mark) = match trend_signal(&prices, config.short_window, config.long_window) {
+      Some(signal) => (signal, mean(&prices[prices.len() - config.short_window..])),
+      None => {
+        orders.revoke(actor, *stock_id);
+        continue;
+      }
+    };
+    let held = *actor.stocks.get(stock_id).unwrap_or(&0);
+
+    if held > 0 {
+      let entry = cost_basis.entry_price(*stock_id).unwrap_or(mark);
+      let stopped = mark <= entry * (1.0 - config.stop_loss);
+      let profited = mark >= entry * (1.0 + config.take_profit);
+      if signal < config.exit_threshold || stopped || profited {
+        orders.exit_offer(actor, *stock_id, mark, held, config.slippage);
+        continue;
+      }
+    }
+    else if signal > config.entry_threshold && positions < config.max_positions {
+      if let Some(committed) = orders.entry_bid(actor, *stock_id, mark, cash, config.position_fraction, config.slippage) {
+        cash = cash.saturating_sub(committed);
+        positions += 1;
+        continue;
+      }
+    }
+    orders.revoke(actor, *stock_id);
+  }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Sender, Receiver, TryRecvError, channel};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::messages::{ActorMessages, MarketMessages};
+use crate::supervisor::{CrashReport, contain};
+
+/*
+Runs many actors on a few worker threads instead of one thread per actor.
+A pooled actor still has its own ActorMessages channel and is registered with the markets
+like any other, only the loop around it is different: every round a worker hands each of
+its actors the messages waiting for it, lets it act once and then sleeps until the next round.
+A pooled actor must never block, a slow one holds up every other actor on its worker.
+*/
+
+pub trait PooledActor: Send {
+  fn id(&self) -> usize;
+  fn kind(&self) -> &str;
+  //Handles one message, returns false once the actor is done, e.g. after Stop.
+  fn receive(&mut self, message: ActorMessages) -> bool;
+  //One pass through the strategy's logic.
+  fn act(&mut self);
+  //What the actor holds if it crashes, with anything it set aside for a settlement given back.
+  fn crash_status(&self) -> String;
+}
+
+struct Pooled {
+  actor: Box<dyn PooledActor>,
+  actor_rx: Receiver<ActorMessages>,
+  markets: HashMap<usize, Sender<MarketMessages>> //Told when the actor crashes
+}
+
+pub struct ActorPool {
+  workers: Vec<(Sender<Pooled>, JoinHandle<Vec<CrashReport>>)>,
+  next_worker: usize
+}
+
+impl ActorPool {
+  pub fn new(worker_count: usize, round: Duration) -> ActorPool {
+    let mut workers = vec![];
+    for _ in 0..worker_count.max(1) {
+      let (worker_tx, worker_rx): (Sender<Pooled>, Receiver<Pooled>) = channel();
+      workers.push((worker_tx, thread::spawn(move || run_worker(worker_rx, round))));
+    }
+    ActorPool {workers, next_worker: 0}
+  }
+
+  //Actors are dealt out to the workers in turn.
+  pub fn spawn(&mut self, actor: Box<dyn PooledActor>, actor_rx: Receiver<ActorMessages>, markets: HashMap<usize, Sender<MarketMessages>>) {
+    let (ref worker_tx, _) = self.workers[self.next_worker];
+    worker_tx.send(Pooled {actor, actor_rx, markets}).unwrap();
+    self.next_worker = (self.next_worker + 1) % self.workers.len();
+  }
+
+  //Waits for every pooled actor to finish and returns the crashes.
+  pub fn join(self) -> Vec<CrashReport> {
+    let mut crashes = vec![];
+    for (worker_tx, handle) in self.workers {
+      drop(worker_tx);
+      match handle.join() {
+        Ok(reports) => {crashes.extend(reports);},
+        Err(_) => {println!("ERROR: an actor pool worker panicked");}
+      }
+    }
+    crashes
+  }
+}
+
+fn run_worker(worker_rx: Receiver<Pooled>, round: Duration) -> Vec<CrashReport> {
+  let mut actors: Vec<Pooled> = vec![];
+  let mut crashes = vec![];
+  let mut accepting = true;
+  loop {
+    while accepting {
+      match worker_rx.try_recv() {
+        Ok(pooled) => {actors.push(pooled);},
+        Err(TryRecvError::Empty) => {break;},
+        Err(TryRecvError::Disconnected) => {accepting = false;}
+      }
+    }
+    if !accepting && actors.is_empty() {
+      return crashes;
+    }
+
+    let began = Instant::now();
+    actors.retain_mut(|pooled| match run_round(pooled) {
+      Ok(running) => running,
+      Err(crash) => {
+        crashes.push(crash);
+        false
+      }
+    });
+    thread::sleep(round.saturating_sub(began.elapsed()));
+  }
+}
+
+//Returns whether the actor keeps running.
+fn run_round(pooled: &mut Pooled) -> Result<bool, CrashReport> {
+  let id = pooled.actor.id();
+  let kind = pooled.actor.kind().to_string();
+  let result = contain(id, &kind, &pooled.markets, || {
+    loop {
+      match pooled.actor_rx.try_recv() {
+        Ok(message) => {
+          if !pooled.actor.receive(message) {
+            return false;
+          }
+        },
+        Err(TryRecvError::Empty) => {break;},
+        Err(TryRecvError::Disconnected) => {
+          println!("ERROR: Actor {} disconnected", id);
+          return false;
+        }
+      }
+    }
+    pooled.actor.act();
+    true
+  });
+  //The actor was not dropped while unwinding, so its holdings are taken from it here.
+  result.map_err(|mut crash| {
+    crash.frozen_status = Some(pooled.actor.crash_status());
+    crash
+  })
+}
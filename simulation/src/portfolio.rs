@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use crate::messages::{MarketHistory, Trade};
+
+/*
+Values every actor's portfolio from the trades the market settled during the run.
+An actor's cash and holdings only change when one of its trades settles, so replaying
+its trades on top of what it started with gives what it ended with.
+
+The market charges a trade's price for the whole quantity, a share is worth price / quantity.
+The seller receives the price less the market's fee.
+Holdings are marked to the last traded unit price of their stock. What an actor started with
+is valued at the unit price when the run began (the last price of a loaded or seeded history,
+otherwise the run's first trade), which is also its cost basis. Realized P&L uses average cost.
+The drawdown follows the actor's net worth after every trade in the market, marked the same way.
+*/
+
+//What an actor was given at the start of the run.
+pub struct Endowment {
+  pub actor_id: usize,
+  pub kind: String,
+  pub money: usize,
+  pub stocks: HashMap<usize, usize> //stock_id, quantity
+}
+
+impl Endowment {
+  pub fn new(actor_id: usize, kind: &str, money: usize) -> Endowment {
+    Endowment {actor_id, kind: kind.to_string(), money, stocks: HashMap::new()}
+  }
+
+  pub fn with_stock(mut self, stock_id: usize, quantity: usize) -> Endowment {
+    *self.stocks.entry(stock_id).or_insert(0) += quantity;
+    self
+  }
+}
+
+pub struct Valuation {
+  pub actor_id: usize,
+  pub kind: String,
+  pub cash: i64,
+  pub holdings: HashMap<usize, usize>,
+  pub start_worth: f64,
+  pub end_worth: f64,
+  pub realized: f64,
+  pub unrealized: f64,
+  pub trades: usize,
+  pub turnover: usize, //Sum of the prices of its trades
+  pub max_drawdown: f64 //Largest fall from a peak net worth, in percent of that peak
+}
+
+impl Valuation {
+  pub fn pnl(&self) -> f64 {
+    self.end_worth - self.start_worth
+  }
+
+  pub fn return_pct(&self) -> f64 {
+    if self.start_worth > 0.0 {100.0 * self.pnl() / self.start_worth} else {0.0}
+  }
+}
+
+//Average P&L per actor type, best first.
+pub struct KindSummary {
+  pub kind: String,
+  pub actors: usize,
+  pub mean_pnl: f64,
+  pub total_pnl: f64,
+  pub trades: usize,
+  pub turnover: usize
+}
+
+struct Position {
+  quantity: usize,
+  cost: f64
+}
+
+//Valuations ranked by P&L, best first.
+pub fn value_portfolios(endowments: &[Endowment], history: &MarketHistory) -> Vec<Valuation> {
+  let run_trades = run_trades(history);
+  let mut opening = HashMap::new();
+  for stock_id in history.stocks.iter() {
+    opening.insert(*stock_id, opening_price(*stock_id, history, &run_trades));
+  }
+  let mut valuations: Vec<Valuation> = endowments.iter().map(|endowment| {
+    value_portfolio(endowment, history, &run_trades, &opening)
+  }).collect();
+  valuations.sort_by(|a, b| b.pnl().total_cmp(&a.pnl()).then(a.actor_id.cmp(&b.actor_id)));
+  valuations
+}
+
+//Replays every trade of the run, the actor's own ones move its cash and positions and all of them move the marks.
+fn value_portfolio(endowment: &Endowment, history: &MarketHistory, run_trades: &[&Trade], opening: &HashMap<usize, f64>) -> Valuation {
+  let mut cash = endowment.money as i64;
+  let mut positions: HashMap<usize, Position> = HashMap::new();
+  let mut marks = opening.clone();
+  let mut start_worth = endowment.money as f64;
+  for (stock_id, quantity) in endowment.stocks.iter() {
+    let open = opening.get(stock_id).cloned().unwrap_or(0.0);
+    start_worth += *quantity as f64 * open;
+    positions.insert(*stock_id, Position {quantity: *quantity, cost: *quantity as f64 * open});
+  }
+
+  let mut realized = 0.0;
+  let mut turnover = 0;
+  let mut trades = 0;
+  let mut peak = start_worth;
+  let mut max_drawdown: f64 = 0.0;
+  for trade in run_trades.iter() {
+    let stock_id = trade.buyer.stock_id;
+    marks.insert(stock_id, unit_price(trade));
+    let involved = trade.buyer.actor_id == endowment.actor_id || trade.seller.actor_id == endowment.actor_id;
+    let held = positions.get(&stock_id).map(|position| position.quantity).unwrap_or(0);
+    if !involved && held == 0 {
+      continue;
+    }
+    if involved {
+      trades += 1;
+      turnover += trade.price();
+    }
+    let price = trade.price();
+    let quantity = trade.quantity();
+    let position = positions.entry(stock_id).or_insert(Position {quantity: 0, cost: 0.0});
+    if trade.buyer.actor_id == endowment.actor_id {
+      cash -= price as i64;
+      position.quantity += quantity;
+      position.cost += price as f64;
+    }
+    if trade.seller.actor_id == endowment.actor_id {
+      let proceeds = price - trade.fee;
+      cash += proceeds as i64;
+      let sold = quantity.min(position.quantity);
+      let average = if position.quantity > 0 {position.cost / position.quantity as f64} else {0.0};
+      realized += proceeds as f64 - average * sold as f64;
+      position.cost -= average * sold as f64;
+      position.quantity -= sold;
+    }
+
+    let worth = cash as f64 + positions.iter().map(|(stock_id, position)| position.quantity as f64 * marks.get(stock_id).cloned().unwrap_or(0.0)).sum::<f64>();
+    peak = peak.max(worth);
+    if peak > 0.0 {
+      max_drawdown = max_drawdown.max(100.0 * (peak - worth) / peak);
+    }
+  }
+
+  let mut end_worth = cash as f64;
+  let mut unrealized = 0.0;
+  let mut holdings = HashMap::new();
+  for (stock_id, position) in positions.iter() {
+    if position.quantity == 0 {
+      continue;
+    }
+    let value = position.quantity as f64 * mark_price(*stock_id, history);
+    end_worth += value;
+    unrealized += value - position.cost;
+    holdings.insert(*stock_id, position.quantity);
+  }
+
+  Valuation {actor_id: endowment.actor_id,
+             kind: endowment.kind.clone(),
+             cash,
+             holdings,
+             start_worth,
+             end_worth,
+             realized,
+             unrealized,
+             trades,
+             turnover,
+             max_drawdown}
+}
+
+pub fn summarize_kinds(valuations: &[Valuation]) -> Vec<KindSummary> {
+  let mut kinds: Vec<KindSummary> = vec![];
+  for valuation in valuations.iter() {
+    let index = match kinds.iter().position(|summary| summary.kind == valuation.kind) {
+      Some(index) => index,
+      None => {
+        kinds.push(KindSummary {kind: valuation.kind.clone(), actors: 0, mean_pnl: 0.0, total_pnl: 0.0, trades: 0, turnover: 0});
+        kinds.len() - 1
+      }
+    };
+    let summary = &mut kinds[index];
+    summary.actors += 1;
+    summary.total_pnl += valuation.pnl();
+    summary.trades += valuation.trades;
+    summary.turnover += valuation.turnover;
+  }
+  for summary in kinds.iter_mut() {
+    summary.mean_pnl = summary.total_pnl / summary.actors as f64;
+  }
+  kinds.sort_by(|a, b| b.mean_pnl.total_cmp(&a.mean_pnl));
+  kinds
+}
+
+//Trades recorded during this run, after anything loaded or seeded before it.
+pub fn run_trades(history: &MarketHistory) -> Vec<&Trade> {
+  let mut trades: Vec<&Trade> = history.history.values().flat_map(|trades| trades.iter()).filter(|trade| trade.time >= history.time_offset).collect();
+  trades.sort_by_key(|trade| trade.seq);
+  trades
+}
+
+fn unit_price(trade: &Trade) -> f64 {
+  if trade.quantity() == 0 {0.0} else {trade.price() as f64 / trade.quantity() as f64}
+}
+
+//The last unit price of the stock before the run began, otherwise its first in the run.
+fn opening_price(stock_id: usize, history: &MarketHistory, run_trades: &[&Trade]) -> f64 {
+  let before_run = history.trades_between(stock_id, 0, history.time_offset);
+  match before_run.last() {
+    Some(trade) => unit_price(trade),
+    None => match history.candles_between(stock_id, 0, history.time_offset).last() {
+      Some(candle) => candle.close as f64,
+      None => run_trades.iter().find(|trade| trade.buyer.stock_id == stock_id).map(|trade| unit_price(trade)).unwrap_or(0.0)
+    }
+  }
+}
+
+pub fn mark_price(stock_id: usize, history: &MarketHistory) -> f64 {
+  match history.trades(stock_id).last() {
+    Some(trade) => unit_price(trade),
+    None => history.last_candle(stock_id).map(|candle| candle.close as f64).unwrap_or(0.0)
+  }
+}
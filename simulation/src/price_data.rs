@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::messages::{Candle, MarketHistory};
+
+/*
+Seeds a MarketHistory with external daily price data before trading begins.
+The directory holds one CSV per listed stock, named after the stock id (0.csv, 1.csv, ...),
+with date, open, high, low and close prices and a volume. A header naming those columns is
+optional, extra columns such as "Adj Close" are ignored. Dates are read as 2020-01-31,
+2020/01/31 or 20200131, optionally followed by a time, and rows are sorted by them so files
+exported newest first load the same way. Any other date format is an error rather than a guess
+between day and month first.
+
+Every row becomes one candle ahead of the run, so strategies see the series through the
+usual candle and indicator queries. Prices are multiplied by scale and rounded, the market
+only trades in whole units.
+*/
+
+pub struct PriceBar {
+  pub date: String,
+  pub open: f64,
+  pub high: f64,
+  pub low: f64,
+  pub close: f64,
+  pub volume: f64
+}
+
+//Loads every stock that has a file in dir, returns how many rows were loaded.
+//With follow_fundamental the closes also become each stock's fundamental value during the run, one per candle interval.
+pub fn seed_history(history: &mut MarketHistory, dir: &str, stock_count: usize, scale: f64, follow_fundamental: bool) -> io::Result<usize> {
+  let start_time = history.time_offset;
+  let interval = history.candle_interval;
+  let mut rows = 0;
+  let mut longest = 0;
+  for stock_id in 0..stock_count {
+    let path = Path::new(dir).join(format!("{}.csv", stock_id));
+    if !path.exists() {
+      continue;
+    }
+    let bars = read_price_csv(&path)?;
+    if !history.stocks.contains(&stock_id) {
+      history.stocks.push(stock_id);
+    }
+    let mut closes = vec![];
+    for (i, bar) in bars.iter().enumerate() {
+      let candle = Candle {start_time: start_time + i * interval,
+                           open: scaled(bar.open, scale),
+                           high: scaled(bar.high, scale),
+                           low: scaled(bar.low, scale),
+                           close: scaled(bar.close, scale),
+                           volume: bar.volume.max(0.0).round() as usize};
+      closes.push(candle.close);
+      history.record_candle(stock_id, candle);
+    }
+    if follow_fundamental {
+      history.fundamentals.insert(stock_id, Arc::new(closes));
+    }
+    rows += bars.len();
+    longest = longest.max(bars.len());
+  }
+  history.stocks.sort();
+  history.time_offset = start_time + longest * interval;
+  Ok(rows)
+}
+
+fn scaled(price: f64, scale: f64) -> usize {
+  (price * scale).max(0.0).round() as usize
+}
+
+pub fn read_price_csv(path: &Path) -> io::Result<Vec<PriceBar>> {
+  let reader = BufReader::new(File::open(path)?);
+  //date, open, high, low, close, volume
+  let mut columns = [0, 1, 2, 3, 4, 5];
+  let mut bars = vec![];
+  for (i, line) in reader.lines().enumerate() {
+    let line = line?;
+    let fields: Vec<&str> = line.trim().split(',').map(|field| field.trim()).collect();
+    if line.trim().is_empty() {
+      continue;
+    }
+    if i == 0 && fields.iter().any(|field| field.eq_ignore_ascii_case("close")) {
+      columns = header_columns(&fields, path)?;
+      continue;
+    }
+    let field = |column: usize| -> io::Result<f64> {
+      match fields.get(columns[column]).map(|value| value.parse::<f64>()) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(invalid(format!("{} line {}: missing or malformed value", path.display(), i + 1)))
+      }
+    };
+    let date = fields.get(columns[0]).unwrap_or(&"").to_string();
+    let day = match parse_date(&date) {
+      Some(day) => day,
+      None => {return Err(invalid(format!("{} line {}: unreadable date \"{}\", expected YYYY-MM-DD", path.display(), i + 1, date)));}
+    };
+    bars.push((day, PriceBar {date,
+                              open: field(1)?,
+                              high: field(2)?,
+                              low: field(3)?,
+                              close: field(4)?,
+                              volume: field(5)?}));
+  }
+  //Stable, so rows sharing a date keep their file order.
+  bars.sort_by_key(|(day, _)| *day);
+  Ok(bars.into_iter().map(|(_, bar)| bar).collect())
+}
+
+//(year, month, day) of a date written year first, anything after the day is ignored.
+fn parse_date(date: &str) -> Option<(u32, u32, u32)> {
+  let day_part = date.split([' ', 'T']).next().unwrap_or("");
+  let parts: Vec<&str> = if day_part.contains('-') {day_part.split('-').collect()}
+                         else if day_part.contains('/') {day_part.split('/').collect()}
+                         else if day_part.len() == 8 && day_part.is_ascii() {vec![&day_part[..4], &day_part[4..6], &day_part[6..]]}
+                         else {vec![]};
+  if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() > 2 || parts[2].len() > 2 || !parts.iter().all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())) {
+    return None;
+  }
+  let (year, month, day) = (parts[0].parse().ok()?, parts[1].parse().ok()?, parts[2].parse().ok()?);
+  if (1..=12).contains(&month) && (1..=31).contains(&day) {Some((year, month, day))} else {None}
+}
+
+fn header_columns(fields: &[&str], path: &Path) -> io::Result<[usize; 6]> {
+  let mut columns = [0; 6];
+  for (i, name) in ["date", "open", "high", "low", "close", "volume"].iter().enumerate() {
+    match fields.iter().position(|field| field.eq_ignore_ascii_case(name)) {
+      Some(column) => {columns[i] = column;},
+      None => {return Err(invalid(format!("{}: no {} column", path.display(), name)));}
+    }
+  }
+  Ok(columns)
+}
+
+fn invalid(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  #[test]
+  fn dates_year_first() {
+    assert_eq!(parse_date("2020-01-31"), Some((2020, 1, 31)));
+    assert_eq!(parse_date("2020/1/2"), Some((2020, 1, 2)));
+    assert_eq!(parse_date("20200131"), Some((2020, 1, 31)));
+    assert_eq!(parse_date("2020-01-31 16:00:00"), Some((2020, 1, 31)));
+    assert_eq!(parse_date("2020-01-31T16:00:00Z"), Some((2020, 1, 31)));
+    assert_eq!(parse_date("01/31/2020"), None);
+    assert_eq!(parse_date("31-01-2020"), None);
+    assert_eq!(parse_date("2020-13-01"), None);
+    assert_eq!(parse_date("Jan 31 2020"), None);
+    assert_eq!(parse_date(""), None);
+  }
+
+  #[test]
+  fn rows_sorted_by_parsed_date() {
+    let path = std::env::temp_dir().join(format!("price_data_test_{}.csv", std::process::id()));
+    fs::write(&path, "Date,Open,High,Low,Close,Volume\n2020-01-10,3,3,3,3,1\n2020-01-09,2,2,2,2,1\n2019-12-31,1,1,1,1,1\n").unwrap();
+    let closes: Vec<f64> = read_price_csv(&path).unwrap().iter().map(|bar| bar.close).collect();
+    fs::write(&path, "2020-01-02,1,1,1,1,1\n02/01/2020,2,2,2,2,1\n").unwrap();
+    let unreadable = read_price_csv(&path);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(closes, vec![1.0, 2.0, 3.0]);
+    match unreadable {
+      Ok(_) => panic!("a day first date was accepted"),
+      Err(error) => assert!(error.to_string().contains("line 2"), "{}", error)
+    }
+  }
+}
@@ -1,196 +1,243 @@
 use std::collections::HashMap;
-use std::rand;
-use std::rand::Rng;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 
-use messages::{MarketMessages, MarketHistory, ActorMessages, TransactionRequest};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
-use actor::Actor;
-use actor::{add_stock, remove_stock, status};
+use crate::messages::{MarketMessages, SharedHistory, ActorMessages, TransactionRequest};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
+use crate::actor::Actor;
+use crate::actor::{add_stock, has_pending_transaction, remove_stock, settled_status, status};
+use crate::pool::PooledActor;
 
-pub fn start_random_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
-  let mut stop_flag = false;
+pub fn start_random_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, seed: u64, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
   let mut init_history = false;
-  let mut rng = rand::thread_rng();
+  let mut rng = StdRng::seed_from_u64(seed);
   println!("Starting Random Actor {}", actor_id);
-  let mut actor = Actor { id: actor_id,
-                          money: 100,
-                          stocks: HashMap::new(),
-                          pending_money: 0,
-                          pending_stock: (0, 0),
-                          markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))};
+  let mut actor = new_random_actor(actor_id, existing_markets, starting_money, &actor_tx);
+
+  loop {
+    if init_history {
+      place_orders(&actor, &mut rng);
+    }
+
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        if !receive(&mut actor, message, &mut init_history) {
+          return;
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+//The same strategy run cooperatively by an ActorPool instead of on its own thread.
+pub struct PooledRandomActor {
+  actor: Actor,
+  rng: StdRng,
+  init_history: bool
+}
+
+impl PooledRandomActor {
+  pub fn new(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, seed: u64, actor_tx: &Sender<ActorMessages>) -> PooledRandomActor {
+    PooledRandomActor {actor: new_random_actor(actor_id, existing_markets, starting_money, actor_tx),
+                       rng: StdRng::seed_from_u64(seed),
+                       init_history: false}
+  }
+}
+
+impl PooledActor for PooledRandomActor {
+  fn id(&self) -> usize {
+    self.actor.id
+  }
+
+  fn kind(&self) -> &str {
+    "Random Actor"
+  }
+
+  fn receive(&mut self, message: ActorMessages) -> bool {
+    receive(&mut self.actor, message, &mut self.init_history)
+  }
+
+  fn act(&mut self) {
+    if self.init_history {
+      place_orders(&self.actor, &mut self.rng);
+    }
+  }
+
+  fn crash_status(&self) -> String {
+    settled_status(&self.actor)
+  }
+}
+
+fn new_random_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, actor_tx: &Sender<ActorMessages>) -> Actor {
+  let actor = Actor { id: actor_id,
+                      money: starting_money,
+                      stocks: HashMap::new(),
+                      pending_money: 0,
+                      pending_stock: (0, 0),
+                      markets: existing_markets,
+                      history: SharedHistory::default()};
 
   for (_, market_tx) in actor.markets.iter() {
     market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
   }
+  actor
+}
 
-  loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
-    //Logic
+//Bids a random price for every stock and offers everything it holds at a random price.
+fn place_orders<R: Rng>(actor: &Actor, rng: &mut R) {
+  for stock in actor.history.snapshot().stocks.iter() {
+      for (_, market_tx) in actor.markets.iter() {
+        if actor.money != 0 {
+          let p = rng.gen::<usize>() % actor.money + 1;
+          let q = rng.gen::<usize>() % 150;
+          let t = TransactionRequest {transaction_id: actor.id, actor_id: actor.id, stock_id: *stock, price: p, quantity: q};
 
-    if init_history {
-      for stock in actor.history.lock().unwrap().stocks.iter() {
-          for (_, market_tx) in actor.markets.iter() {
-            if actor.money != 0 {
-              let p = rng.gen::<usize>() % actor.money + 1;
-              let q = rng.gen::<usize>() % 150;
-              let t = TransactionRequest {transaction_id: actor.id, actor_id: actor.id, stock_id: *stock, price: p, quantity: q};
-
-              market_tx.send(BuyRequest(t)).unwrap();
-            }
-          }
+          market_tx.send(BuyRequest(t)).unwrap();
+        }
       }
-      for (stock, count) in actor.stocks.iter() {
-        for (_, market_tx) in actor.markets.iter() {
-          if *count != 0  {
-            let p = rng.gen::<usize>() % 150 + 1;
-            let q = rng.gen::<usize>() % *count + 1;
-            let t = TransactionRequest {transaction_id: actor.id, actor_id: actor.id, stock_id: *stock, price: p, quantity: q};
-
-            if q != 0 {
-              market_tx.send(SellRequest(t)).unwrap();
-            }
-          }
+  }
+  for (stock, count) in actor.stocks.iter() {
+    for (_, market_tx) in actor.markets.iter() {
+      if *count != 0  {
+        let p = rng.gen::<usize>() % 150 + 1;
+        let q = rng.gen::<usize>() % *count + 1;
+        let t = TransactionRequest {transaction_id: actor.id, actor_id: actor.id, stock_id: *stock, price: p, quantity: q};
+
+        if q != 0 {
+          market_tx.send(SellRequest(t)).unwrap();
         }
       }
     }
+  }
+}
 
-    let mark_clone = actor.markets.clone();
-    let stock_clone = actor.stocks.clone();
-
-    match actor_rx.try_recv() {
-      Ok(message) => {
-          match message {
-            StockRequest(stock_request) => {
-              let market_tx;
-              let tx_text = mark_clone.get(&stock_request.market_id);
-              match tx_text {
-                Some(tx) => {
-                  market_tx = tx;
-                },
-                None => {
-                  return; //HOW DID WE GET A MISSING MARKET?
-                }
-              }
-              if has_pending_transaction(&actor) {
-                market_tx.send(Cancel(actor.id)).unwrap();
-              }
-              else {
-                //if we have the stock. set it aside.
-                let stock_id = stock_request.stock_id;
-                let quantity = stock_request.quantity;
-                let stock = stock_clone.get(&stock_id);
-                match stock {
-                  Some(owned_quantity) => {
-                    if *owned_quantity >= quantity {
-                      remove_stock(&mut actor, (stock_id, quantity));
-                      actor.pending_stock = (stock_id, quantity);
-                      market_tx.send(Commit(actor.id)).unwrap();
-                    }
-                    else {
-                      market_tx.send(Cancel(actor.id)).unwrap();
-                    }
-                  },
-                  None => {
-                    market_tx.send(Cancel(actor.id)).unwrap();
-                  }
-                }
-              }
-              // print_status(&actor);
-              },
-            MoneyRequest(money_request) => {
-              let market_tx;
-              let tx_text = actor.markets.get(&money_request.market_id);
-              match tx_text {
-                Some(tx) => {
-                  market_tx = tx;
-                },
-                None => {
-                  return; //HOW DID WE GET A MISSING MARKET?
-                }
-              }
-              if has_pending_transaction(&actor) {
-                market_tx.send(Cancel(actor.id)).unwrap();
-              }
-              else {
-                //if we have the money. set it aside.
-                if actor.money >= money_request.amount && actor.money > 0 {
-                  actor.money = actor.money - money_request.amount;
-                  actor.pending_money = money_request.amount;
-                  market_tx.send(Commit(actor.id)).unwrap();
-                }
-                else {
-                  market_tx.send(Cancel(actor.id)).unwrap();
-                }
-              }
-              // print_status(&actor);
-              },
-            CommitTransaction(commit_transaction_request) => {
-              //if we have money pending, then look up the stock id and add that quantity purchased.
-              //remove the pending money
-              if actor.pending_money > 0 {
-                let units = commit_transaction_request.quantity;
-                let leftover_money = actor.pending_money - commit_transaction_request.price;
-
-                //make a function for adding stock.
-                add_stock(&mut actor, (commit_transaction_request.stock_id, units));
-                actor.money = actor.money + leftover_money;
-
-                actor.pending_money = 0;
-              }
-
-              //if we have stock pending, look up the quantity purchased and add the money.
-              //remove the pending stock
-              if actor.pending_stock.1 > 0 {
-                let money = commit_transaction_request.price;
-                let restore_stock = (commit_transaction_request.stock_id, actor.pending_stock.1 - commit_transaction_request.quantity);
-                if restore_stock.1 > 0 {
-                  add_stock(&mut actor, restore_stock);
-                }
-                actor.money = actor.money + money;
-                actor.pending_stock = (0,0);
-              }
-            },
-            AbortTransaction => {
-              //move pending stock back into stocks.
-              if actor.pending_stock.1 != 0 {
-                let pending_stock_clone = actor.pending_stock.clone();
-                add_stock(&mut actor, pending_stock_clone);
-                //now that we have moved it. Clear out the pending stock.
-                actor.pending_stock = (0,0); //setting the quantity to zero clears it.
-              }
-              //move pending money back into money.
-              if actor.pending_money > 0 {
-                actor.money = actor.money + actor.pending_money;
-                actor.pending_money = 0;
-              }
-            },
-            History(history) => {
-              actor.history = history;
-              init_history = true;},
-            Time(_, _) => {},
-            ReceiveActivityCount(_,_,_) => {},
-            Stop(main_channel) => {
-              main_channel.send((actor.id, "(Random Actor) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+//Returns false once the actor is done.
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_match, clippy::needless_late_init)]
+fn receive(actor: &mut Actor, message: ActorMessages, init_history: &mut bool) -> bool {
+  let mark_clone = actor.markets.clone();
+  let stock_clone = actor.stocks.clone();
+  match message {
+    StockRequest(stock_request) => {
+      let market_tx;
+      let tx_text = mark_clone.get(&stock_request.market_id);
+      match tx_text {
+        Some(tx) => {
+          market_tx = tx;
+        },
+        None => {
+          return false; //HOW DID WE GET A MISSING MARKET?
+        }
+      }
+      if has_pending_transaction(actor) {
+        market_tx.send(Cancel(actor.id)).unwrap();
+      }
+      else {
+        //if we have the stock. set it aside.
+        let stock_id = stock_request.stock_id;
+        let quantity = stock_request.quantity;
+        let stock = stock_clone.get(&stock_id);
+        match stock {
+          Some(owned_quantity) => {
+            if *owned_quantity >= quantity {
+              remove_stock(actor, (stock_id, quantity));
+              actor.pending_stock = (stock_id, quantity);
+              market_tx.send(Commit(actor.id)).unwrap();
             }
+            else {
+              market_tx.send(Cancel(actor.id)).unwrap();
+            }
+          },
+          None => {
+            market_tx.send(Cancel(actor.id)).unwrap();
           }
+        }
+      }
+      // print_status(actor);
+      },
+    MoneyRequest(money_request) => {
+      let market_tx;
+      let tx_text = actor.markets.get(&money_request.market_id);
+      match tx_text {
+        Some(tx) => {
+          market_tx = tx;
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(1));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id);}
+        None => {
+          return false; //HOW DID WE GET A MISSING MARKET?
+        }
+      }
+      if has_pending_transaction(actor) {
+        market_tx.send(Cancel(actor.id)).unwrap();
+      }
+      else {
+        //if we have the money. set it aside.
+        if actor.money >= money_request.amount && actor.money > 0 {
+          actor.money = actor.money - money_request.amount;
+          actor.pending_money = money_request.amount;
+          market_tx.send(Commit(actor.id)).unwrap();
+        }
+        else {
+          market_tx.send(Cancel(actor.id)).unwrap();
+        }
+      }
+      // print_status(actor);
+      },
+    CommitTransaction(commit_transaction_request) => {
+      //if we have money pending, then look up the stock id and add that quantity purchased.
+      //remove the pending money
+      if actor.pending_money > 0 {
+        let units = commit_transaction_request.quantity;
+        let leftover_money = actor.pending_money - commit_transaction_request.price;
+
+        //make a function for adding stock.
+        add_stock(actor, (commit_transaction_request.stock_id, units));
+        actor.money = actor.money + leftover_money;
+
+        actor.pending_money = 0;
+      }
+
+      //if we have stock pending, look up the quantity purchased and add the money.
+      //remove the pending stock
+      if actor.pending_stock.1 > 0 {
+        let money = commit_transaction_request.price;
+        let restore_stock = (commit_transaction_request.stock_id, actor.pending_stock.1 - commit_transaction_request.quantity);
+        if restore_stock.1 > 0 {
+          add_stock(actor, restore_stock);
+        }
+        actor.money = actor.money + money;
+        actor.pending_stock = (0,0);
+      }
+    },
+    AbortTransaction => {
+      //move pending stock back into stocks.
+      if actor.pending_stock.1 != 0 {
+        let pending_stock_clone = actor.pending_stock.clone();
+        add_stock(actor, pending_stock_clone);
+        //now that we have moved it. Clear out the pending stock.
+        actor.pending_stock = (0,0); //setting the quantity to zero clears it.
+      }
+      //move pending money back into money.
+      if actor.pending_money > 0 {
+        actor.money = actor.money + actor.pending_money;
+        actor.pending_money = 0;
+      }
+    },
+    History(history) => {
+      actor.history = history;
+      *init_history = true;},
+    Time(_, _) => {},
+    ReceiveActivityCount(_,_,_) => {},
+    Stop(main_channel) => {
+      main_channel.send((actor.id, "(Random Actor) ".to_string() + status(actor).as_str())).unwrap();
+      return false;
     }
   }
-}
-
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
+  true
 }
@@ -0,0 +1,225 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::messages::{ActorMessages, MarketMessages, SharedHistory, TransactionRequest};
+use crate::messages::MarketMessages::{BuyRequest, SellRequest, Commit, Cancel, RegisterActor, RevokeRequest, RequestActivityCount, ActorCrashed, Shutdown, Time};
+use crate::event_log::{Event, EventLog, LogEntry, read_event_log};
+use crate::market;
+
+/*
+Feeds the orders of a recorded event log back through a fresh market and its tellers.
+There are no live actors: each recorded actor is stood in for by a proxy that commits
+exactly the trades that settled in the original run and cancels everything else, so any
+difference in the outcome comes from the matching and settlement engine itself.
+*/
+
+// (Buyer's, Seller's) request -> how many more times it may settle
+type TradeCounts = HashMap<(TransactionRequest, TransactionRequest), usize>;
+
+//Registered like an actor so the tellers' activity counts can be used as a barrier.
+const BARRIER_ID: usize = usize::MAX;
+
+//Replays the log at original_path, writing the replay's own log to replay_path.
+//Returns whether the replay produced the same trades as the original.
+pub fn replay(original_path: &str, replay_path: &str) -> io::Result<bool> {
+  let original = read_event_log(original_path)?;
+  let (market_id, stock_count, fee_bps) = match original.iter().find_map(|entry| match entry.event {
+      Event::Open {stock_count, fee_bps} => Some((entry.market_id, stock_count, fee_bps)),
+      _ => None
+    }) {
+    Some(opened) => opened,
+    None => {return Err(io::Error::new(io::ErrorKind::InvalidData, "event log has no Open entry"));}
+  };
+  let replay_log = EventLog::create(replay_path)?;
+
+  let settled = settled_counts(&original);
+  //The buyer's and the seller's proxy each consume their own copy.
+  let decisions = Arc::new(Mutex::new((settled.clone(), settled.clone())));
+  let responses = Arc::new(AtomicUsize::new(0));
+
+  let (market_tx, market_rx): (Sender<MarketMessages>, Receiver<MarketMessages>) = channel();
+  let market_tx_clone = market_tx.clone();
+  let history = SharedHistory::default();
+  let market_handle = thread::spawn(move || {
+    market::start_market(market_id, market_tx_clone, market_rx, stock_count, fee_bps, history, Some(replay_log));});
+
+  let mut proxies = vec![];
+  for actor_id in actor_ids(&original) {
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    market_tx.send(RegisterActor(actor_id, actor_tx.clone())).unwrap();
+    let proxy_market_tx = market_tx.clone();
+    let proxy_decisions = decisions.clone();
+    let proxy_responses = responses.clone();
+    proxies.push((actor_tx, thread::spawn(move || {
+      start_proxy_actor(actor_id, proxy_market_tx, actor_rx, proxy_decisions, proxy_responses);})));
+  }
+  let (barrier_tx, barrier_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+  market_tx.send(RegisterActor(BARRIER_ID, barrier_tx)).unwrap();
+
+  for entry in original.iter().filter(|entry| entry.market_id == market_id) {
+    match entry.event {
+      Event::BuyOrder {ref request} => {market_tx.send(BuyRequest(request.clone())).unwrap();},
+      Event::SellOrder {ref request} => {market_tx.send(SellRequest(request.clone())).unwrap();},
+      Event::Revoke {stock_id, actor_id, transaction_id} => {market_tx.send(RevokeRequest(stock_id, actor_id, transaction_id)).unwrap();},
+      Event::Tick {max_time} => {market_tx.send(Time(entry.time, max_time)).unwrap();},
+      Event::Crashed {actor_id} => {market_tx.send(ActorCrashed(actor_id)).unwrap();},
+      Event::Close => {break;},
+      _ => {}
+    }
+  }
+
+  //Settling one trade can activate the next pending one, so wait until a full
+  //round through the tellers and the market passes without any proxy answering.
+  loop {
+    let before = responses.load(Ordering::SeqCst);
+    barrier(&market_tx, &barrier_rx, stock_count);
+    barrier(&market_tx, &barrier_rx, stock_count);
+    thread::sleep(Duration::from_millis(20));
+    if responses.load(Ordering::SeqCst) == before {
+      break;
+    }
+  }
+
+  let (closed_tx, closed_rx): (Sender<usize>, Receiver<usize>) = channel();
+  market_tx.send(Shutdown(closed_tx)).unwrap();
+  closed_rx.recv().unwrap();
+  drop(market_tx);
+  let (stop_tx, _stop_rx) = channel::<(usize, String)>();
+  for (actor_tx, handle) in proxies {
+    let _ = actor_tx.send(ActorMessages::Stop(stop_tx.clone()));
+    handle.join().unwrap();
+  }
+  drop(barrier_rx);
+  market_handle.join().unwrap();
+
+  let replayed = read_event_log(replay_path)?;
+  Ok(compare(&original, &replayed))
+}
+
+fn start_proxy_actor(actor_id: usize, market_tx: Sender<MarketMessages>, actor_rx: Receiver<ActorMessages>, decisions: Arc<Mutex<(TradeCounts, TradeCounts)>>, responses: Arc<AtomicUsize>) {
+  while let Ok(message) = actor_rx.recv() {
+    let (transaction, buying) = match message {
+      ActorMessages::MoneyRequest(request) => (request.transaction, true),
+      ActorMessages::StockRequest(request) => (request.transaction, false),
+      ActorMessages::Stop(_) => {return;},
+      _ => {continue;}
+    };
+    let settle = {
+      let mut d = decisions.lock().unwrap();
+      let counts = if buying {&mut d.0} else {&mut d.1};
+      match counts.get_mut(&transaction) {
+        Some(count) if *count > 0 => {*count -= 1; true},
+        _ => false
+      }
+    };
+    if settle {
+      market_tx.send(Commit(actor_id)).unwrap();
+    }
+    else {
+      market_tx.send(Cancel(actor_id)).unwrap();
+    }
+    responses.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
+//Returns once every teller has handled everything the market forwarded before the call.
+fn barrier(market_tx: &Sender<MarketMessages>, barrier_rx: &Receiver<ActorMessages>, stock_count: usize) {
+  for stock_id in 0..stock_count {
+    market_tx.send(RequestActivityCount(BARRIER_ID, stock_id, true)).unwrap();
+  }
+  let mut answered = 0;
+  while answered < stock_count {
+    if let ActorMessages::ReceiveActivityCount(_, _, _) = barrier_rx.recv().unwrap() {answered += 1;}
+  }
+}
+
+fn actor_ids(entries: &[LogEntry]) -> BTreeSet<usize> {
+  let mut ids = BTreeSet::new();
+  for entry in entries.iter() {
+    match entry.event {
+      Event::BuyOrder {ref request} | Event::SellOrder {ref request} => {ids.insert(request.actor_id);},
+      _ => {}
+    }
+  }
+  ids
+}
+
+fn settled_counts(entries: &[LogEntry]) -> TradeCounts {
+  let mut counts = HashMap::new();
+  for entry in entries.iter() {
+    if let Event::Settled {ref buyer, ref seller} = entry.event {
+      *counts.entry((buyer.clone(), seller.clone())).or_insert(0) += 1;
+    }
+  }
+  counts
+}
+
+fn matches_by_stock(entries: &[LogEntry]) -> HashMap<usize, Vec<(TransactionRequest, TransactionRequest)>> {
+  let mut matches = HashMap::new();
+  for entry in entries.iter() {
+    if let Event::Match {ref buyer, ref seller} = entry.event {
+      matches.entry(buyer.stock_id).or_insert_with(Vec::new).push((buyer.clone(), seller.clone()));
+    }
+  }
+  matches
+}
+
+//The original's matches for each stock must be a prefix of the replay's: a teller that
+//was still working through its backlog when the original market closed never got to the rest.
+fn compare(original: &[LogEntry], replayed: &[LogEntry]) -> bool {
+  let mut mismatches = vec![];
+  let original_matches = matches_by_stock(original);
+  let replayed_matches = matches_by_stock(replayed);
+  let mut checked = 0;
+  let mut extra = 0;
+  let no_matches = vec![];
+  for (stock_id, expected) in original_matches.iter() {
+    let actual = replayed_matches.get(stock_id).unwrap_or(&no_matches);
+    match expected.iter().zip(actual.iter()).position(|(e, a)| e != a) {
+      Some(i) => {
+        mismatches.push(format!("stock {}: match {} was {} / {} in the original but {} / {} in the replay",
+          stock_id, i, expected[i].0, expected[i].1, actual[i].0, actual[i].1));
+      },
+      None if actual.len() < expected.len() => {
+        mismatches.push(format!("stock {}: the original had {} matches, the replay only {}", stock_id, expected.len(), actual.len()));
+      },
+      None => {
+        checked += expected.len();
+        extra += actual.len() - expected.len();
+      }
+    }
+  }
+  for (stock_id, actual) in replayed_matches.iter() {
+    if !original_matches.contains_key(stock_id) {
+      extra += actual.len();
+    }
+  }
+
+  let original_settled = settled_counts(original);
+  let replayed_settled = settled_counts(replayed);
+  for (trade, count) in original_settled.iter() {
+    let replayed_count = *replayed_settled.get(trade).unwrap_or(&0);
+    if replayed_count != *count {
+      mismatches.push(format!("trade {} / {} settled {} times in the original, {} in the replay", trade.0, trade.1, count, replayed_count));
+    }
+  }
+  for (trade, count) in replayed_settled.iter() {
+    if !original_settled.contains_key(trade) {
+      mismatches.push(format!("trade {} / {} settled {} times in the replay but never in the original", trade.0, trade.1, count));
+    }
+  }
+
+  println!("\n\nReplay Result\n-------------");
+  println!("{} matches identical, {} further matches past the end of the original", checked, extra);
+  println!("{} settled trades in the original, {} in the replay",
+    original_settled.values().sum::<usize>(), replayed_settled.values().sum::<usize>());
+  for mismatch in mismatches.iter() {
+    println!("MISMATCH: {}", mismatch);
+  }
+  mismatches.is_empty()
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+use crate::messages::MarketHistory;
+use crate::portfolio::{KindSummary, Valuation};
+use crate::batch::StrategyStats;
+use crate::market_stats::{MarketStats, StockSummary};
+use crate::zi_trader::Efficiency;
+use crate::fundamental::Tracking;
+
+/*
+End of run summaries printed after the actors' final status.
+*/
+
+pub fn print_candles(history: &MarketHistory) {
+  println!("\nCandles (interval {})\n-------", history.candle_interval);
+  for stock_id in history.stocks.iter() {
+    println!("Stock {}", stock_id);
+    for candle in history.candles(*stock_id).iter() {
+      println!("  t: {}, O: {}, H: {}, L: {}, C: {}, V: {}",
+        candle.start_time, candle.open, candle.high, candle.low, candle.close, candle.volume);
+    }
+  }
+}
+
+pub fn print_indicators(history: &MarketHistory) {
+  println!("\nIndicators\n----------");
+  for stock_id in history.stocks.iter() {
+    match history.indicators(*stock_id) {
+      Some(i) => {
+        println!("Stock {}: SMA: {}, EMA: {}, VWAP: {}, RSI: {}, Volatility: {}", stock_id,
+          format_value(i.sma()), format_value(i.ema()), format_value(i.vwap()), format_value(i.rsi()), format_value(i.volatility()));
+        if let Some(b) = i.bollinger() {println!("  Bollinger: {:.2} / {:.2} / {:.2}", b.lower, b.middle, b.upper);}
+        if let Some(m) = i.macd() {println!("  MACD: {:.2}, Signal: {:.2}, Histogram: {:.2}", m.macd, m.signal, m.histogram);}
+      },
+      None => {println!("Stock {}: no trades", stock_id);}
+    }
+  }
+}
+
+fn format_value(value: Option<f64>) -> String {
+  match value {
+    Some(v) => format!("{:.2}", v),
+    None => String::from("-")
+  }
+}
+
+pub fn print_portfolios(valuations: &[Valuation], kinds: &[KindSummary]) {
+  println!("\nPortfolios (ranked by P&L, holdings marked to the last traded price)\n----------");
+  println!("{:>4} {:>6} {:<20} {:>10} {:>10} {:>10} {:>8} {:>10} {:>10} {:>7} {:>9} {:>7}",
+    "rank", "id", "type", "start", "end", "P&L", "return", "realized", "unrealized", "trades", "turnover", "max DD");
+  for (rank, v) in valuations.iter().enumerate() {
+    println!("{:>4} {:>6} {:<20} {:>10.2} {:>10.2} {:>10.2} {:>7.1}% {:>10.2} {:>10.2} {:>7} {:>9} {:>6.1}%",
+      rank + 1, v.actor_id, v.kind, v.start_worth, v.end_worth, v.pnl(), v.return_pct(), v.realized, v.unrealized, v.trades, v.turnover, v.max_drawdown);
+  }
+  println!("\nBy actor type\n-------------");
+  println!("{:>4} {:<20} {:>6} {:>10} {:>10} {:>7} {:>9}", "rank", "type", "actors", "mean P&L", "total P&L", "trades", "turnover");
+  for (rank, k) in kinds.iter().enumerate() {
+    println!("{:>4} {:<20} {:>6} {:>10.2} {:>10.2} {:>7} {:>9}", rank + 1, k.kind, k.actors, k.mean_pnl, k.total_pnl, k.trades, k.turnover);
+  }
+}
+
+pub fn print_market_stats(summaries: &[StockSummary]) {
+  println!("\nMarket statistics (best bid, ask and spread per share)\n-----------------");
+  println!("{:>5} {:>7} {:>7} {:>7} {:>8} {:>7} {:>7} {:>8} {:>7} {:>7} {:>7} {:>7} {:>10}",
+    "stock", "orders", "trades", "volume", "ord/trd", "cancel", "aborts", "abort %", "bid", "ask", "spread", "latency", "volatility");
+  for s in summaries.iter() {
+    println!("{:>5} {:>7} {:>7} {:>7} {:>8} {:>6.1}% {:>7} {:>7.1}% {:>7} {:>7} {:>7} {:>7} {:>10.4}",
+      s.stock_id, s.orders, s.trades, s.volume, format_value(s.order_to_trade()), s.cancel_rate(), s.aborts, s.abort_rate(),
+      format_value(s.mean_bid), format_value(s.mean_ask), format_value(s.mean_spread), format_value(s.mean_latency), s.volatility);
+  }
+}
+
+pub fn write_tick_stats_csv(stats: &MarketStats, path: &str) -> io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+  writeln!(writer, "time,stock_id,buy_orders,sell_orders,revokes,matches,aborts,trades,volume,best_bid,best_ask,spread")?;
+  let optional = |value: Option<f64>| value.map(|v| format!("{:.4}", v)).unwrap_or_default();
+  for ((time, stock_id), tick) in stats.ticks.iter() {
+    writeln!(writer, "{},{},{},{},{},{},{},{},{},{},{},{}",
+      time, stock_id, tick.buy_orders, tick.sell_orders, tick.revokes, tick.matches, tick.aborts, tick.trades, tick.volume,
+      optional(tick.best_bid), optional(tick.best_ask), optional(tick.spread()))?;
+  }
+  writer.flush()
+}
+
+pub fn print_leaderboard(board: &[StrategyStats], runs: usize) {
+  println!("\nLeaderboard over {} runs (returns in percent, 95% intervals over runs)\n-----------", runs);
+  println!("{:>4} {:<20} {:>7} {:>8} {:>8} {:>8} {:>19} {:>6} {:>15} {:>9} {:>9}",
+    "rank", "type", "samples", "mean", "median", "stddev", "mean 95% CI", "win %", "win 95% CI", "mean DD", "max DD");
+  for (rank, s) in board.iter().enumerate() {
+    println!("{:>4} {:<20} {:>7} {:>8.1} {:>8.1} {:>8.1} {:>19} {:>6.1} {:>15} {:>8.1}% {:>8.1}%",
+      rank + 1, s.kind, s.samples, s.mean_return, s.median_return, s.stddev_return, format_interval(s.return_ci),
+      s.win_rate, format_interval(s.win_rate_ci), s.mean_drawdown, s.max_drawdown);
+  }
+}
+
+pub fn print_tracking(tracking: &[Tracking]) {
+  println!("\nPrices against fundamental values (errors in percent of the value)\n-------------------------------");
+  println!("{:>5} {:>7} {:>9} {:>9} {:>9} {:>11} {:>11} {:>11}", "stock", "trades", "mean err", "rms err", "within 10", "correlation", "final price", "final value");
+  for t in tracking.iter() {
+    println!("{:>5} {:>7} {:>8.1}% {:>8.1}% {:>8.1}% {:>11} {:>11} {:>11}", t.stock_id, t.trades, t.mean_abs_error, t.rms_error, t.within_10,
+             t.correlation.map(|c| format!("{:.3}", c)).unwrap_or("-".to_string()),
+             t.final_price.map(|p| format!("{:.2}", p)).unwrap_or("-".to_string()),
+             t.final_value.map(|v| v.to_string()).unwrap_or("-".to_string()));
+  }
+}
+
+pub fn print_efficiency(efficiency: &Efficiency) {
+  println!("\nZI-C market efficiency\n----------------------");
+  match efficiency.equilibrium_prices {
+    Some((low, high)) => {println!("Competitive equilibrium: {} units at {} to {}", efficiency.equilibrium_quantity, low, high);},
+    None => {println!("Competitive equilibrium: no unit is worth more to a buyer than it costs a seller");}
+  }
+  match efficiency.mean_price {
+    Some(price) => {println!("Traded: {} units at a mean price of {:.2}", efficiency.traded, price);},
+    None => {println!("Traded: nothing");}
+  }
+  match efficiency.efficiency() {
+    Some(percent) => {println!("Surplus: {:.1} realized of {} possible, {:.1}% efficiency", efficiency.realized, efficiency.maximum, percent);},
+    None => {println!("Surplus: {:.1} realized, none was possible", efficiency.realized);}
+  }
+}
+
+fn format_interval((low, high): (f64, f64)) -> String {
+  format!("[{:.1}, {:.1}]", low, high)
+}
+
+pub fn write_candles_csv(history: &MarketHistory, path: &str) -> io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+  writeln!(writer, "stock_id,start_time,open,high,low,close,volume")?;
+  for stock_id in history.stocks.iter() {
+    for candle in history.candles(*stock_id).iter() {
+      writeln!(writer, "{},{},{},{},{},{},{}",
+        stock_id, candle.start_time, candle.open, candle.high, candle.low, candle.close, candle.volume)?;
+    }
+  }
+  writer.flush()
+}
@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::messages::{ActorMessages, MarketHistory, MarketMessages, SharedHistory};
+use crate::actor::{start_actor, STARTING_MONEY};
+use crate::corporate_actor::start_corporate_actor;
+use crate::scripted_actor::start_scripted_actor;
+use crate::smarter_actor::start_smarter_actor;
+use crate::random_actor::{start_random_actor, PooledRandomActor};
+use crate::dummy_actor_1::start_dummy_actor_1;
+use crate::dummy_actor_2::start_dummy_actor_2;
+use crate::market_maker::{start_market_maker, MarketMakerConfig};
+use crate::momentum_actor::{start_momentum_actor, MomentumConfig};
+use crate::mean_reversion_actor::{start_mean_reversion_actor, MeanReversionConfig};
+use crate::zi_trader::{draw_traders, start_zi_trader, ZiConfig, ZiTrader};
+use crate::informed_actor::{start_informed_actor, InformedConfig};
+use crate::fundamental::{self, FundamentalConfig};
+use crate::script::Script;
+use crate::strategy_actor::start_strategy_actor;
+use crate::supervisor::{supervise, CrashReport};
+use crate::event_log::EventLog;
+use crate::market;
+use crate::market_stats::MarketStats;
+use crate::pool::ActorPool;
+use crate::portfolio::Endowment;
+
+/*
+One trading session: a market, the actors trading on it and the clock driving them.
+main runs a single scenario, batch mode runs the same one many times with different seeds.
+The seed decides every random choice the actors make, thread scheduling still varies between runs.
+*/
+
+//Every type of actor a scenario starts, as named in reports.
+pub const ACTOR_KINDS: [&str; 14] = ["Standard Actor", "Corporate Actor", "Scripted Actor", "Smarter Actor",
+                                     "Random Actor", "Dumb Actor 1", "Dumb Actor 2", "Market Maker", "Momentum Actor",
+                                     "Mean Reversion Actor", "ZI-C Buyer", "ZI-C Seller", "Informed Actor", "Strategy Actor"];
+
+#[derive(Clone)]
+pub struct Scenario {
+  pub starting_money: usize, //Every actor's cash at the start
+  pub standard_actors: usize,
+  pub corporate_actors: usize,
+  pub corporate_starting_quantity: usize, //Each corporate actor starts with this much of its own stock
+  pub scripted_actors: usize,
+  pub script: Option<Arc<Script>>, //What the scripted actors follow instead of their built in script
+  pub smarter_actors: usize,
+  pub random_actors: usize,
+  pub dummy_actor_1s: usize,
+  pub dummy_actor_2s: usize,
+  pub market_makers: usize,
+  pub market_maker: MarketMakerConfig,
+  pub momentum_actors: usize,
+  pub momentum: MomentumConfig,
+  pub mean_reversion_actors: usize,
+  pub mean_reversion: MeanReversionConfig,
+  pub zi: ZiConfig, //Its buyers and sellers are the ZI-C population
+  pub fundamental: Option<FundamentalConfig>, //Generates every stock's hidden value, see fundamental.rs
+  pub informed_actors: usize,
+  pub informed: InformedConfig,
+  pub strategy_actors: usize,
+  pub strategy: Option<Arc<String>>, //Rhai source the strategy actors run, see strategy_actor.rs
+  pub pooled_random_actors: usize, //Run on a few worker threads instead of a thread each, see pool.rs.
+  pub workers: usize,
+  pub pool_round: Duration,
+  pub tick: usize, //Milliseconds between Time messages
+  pub ticks: usize,
+  pub fee_bps: usize, //Taken from the seller's proceeds of every trade, in basis points
+  pub seed: u64
+}
+
+impl Default for Scenario {
+  fn default() -> Scenario {
+    Scenario {starting_money: STARTING_MONEY,
+              standard_actors: 5,
+              corporate_actors: 5,
+              corporate_starting_quantity: 100,
+              scripted_actors: 10,
+              script: None,
+              smarter_actors: 5,
+              random_actors: 15,
+              dummy_actor_1s: 4,
+              dummy_actor_2s: 4,
+              market_makers: 0,
+              market_maker: MarketMakerConfig::default(),
+              momentum_actors: 3,
+              momentum: MomentumConfig::default(),
+              mean_reversion_actors: 0,
+              mean_reversion: MeanReversionConfig::default(),
+              zi: ZiConfig::default(),
+              fundamental: None,
+              informed_actors: 0,
+              informed: InformedConfig::default(),
+              strategy_actors: 0,
+              strategy: None,
+              pooled_random_actors: 0,
+              workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+              pool_round: Duration::from_millis(100),
+              tick: 100,
+              ticks: 248,
+              fee_bps: 0,
+              seed: 0}
+  }
+}
+
+pub struct RunResult {
+  pub history: Arc<MarketHistory>,
+  pub endowments: Vec<Endowment>,
+  pub responses: Vec<String>, //Final status of every actor, by id
+  pub failed: Vec<String>, //Threads that exited with a panic
+  pub market_stats: MarketStats,
+  pub zi_traders: Vec<ZiTrader> //Private values and costs the ZI-C traders were given
+}
+
+impl Scenario {
+  //Stocks the market lists, one per corporate actor and at least the one ZI-C traders trade.
+  pub fn stock_count(&self) -> usize {
+    if self.zi.buyers + self.zi.sellers > 0 {self.corporate_actors.max(self.zi.stock_id + 1)} else {self.corporate_actors}
+  }
+
+  //Leaves the ZI-C traders alone in the market, as in the experiments they come from.
+  pub fn only_zi(&mut self) {
+    self.standard_actors = 0;
+    self.corporate_actors = 0;
+    self.scripted_actors = 0;
+    self.smarter_actors = 0;
+    self.random_actors = 0;
+    self.dummy_actor_1s = 0;
+    self.dummy_actor_2s = 0;
+    self.market_makers = 0;
+    self.momentum_actors = 0;
+    self.mean_reversion_actors = 0;
+    self.informed_actors = 0;
+    self.strategy_actors = 0;
+    self.pooled_random_actors = 0;
+  }
+}
+
+//Derives an actor's own seed from the scenario's, so actors don't share a random sequence.
+pub fn actor_seed(seed: u64, actor_id: usize) -> u64 {
+  seed ^ (actor_id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+//A scenario whose market and actors are running. Its clock only moves on tick, see run_scenario.
+pub struct Session {
+  markets: HashMap<usize, Sender<MarketMessages>>,
+  tx_market: Sender<MarketMessages>,
+  market_handle: JoinHandle<MarketStats>,
+  history: SharedHistory,
+  actors_with_timers: Vec<Sender<ActorMessages>>,
+  actor_handles: Vec<(usize, JoinHandle<Option<CrashReport>>)>,
+  pool: ActorPool,
+  endowments: Vec<Endowment>,
+  zi_traders: Vec<ZiTrader>,
+  next_id: usize,
+  tick: usize,
+  ticks: usize
+}
+
+pub fn run_scenario(scenario: &Scenario, history: MarketHistory, event_log: Option<EventLog>) -> RunResult {
+  let session = start_session(scenario, history, event_log);
+  for t in 0..scenario.ticks {
+    session.tick(t);
+  }
+  session.finish()
+}
+
+//Starts the market and every actor of the scenario, with the clock at 0.
+pub fn start_session(scenario: &Scenario, mut history: MarketHistory, event_log: Option<EventLog>) -> Session {
+  if let Some(config) = &scenario.fundamental {
+    fundamental::generate(&mut history, config, scenario.stock_count(), scenario.ticks * scenario.tick, scenario.seed);
+  }
+  //tx: clone for actors        rx: owned by market
+  //TODO: with spawning multiple markets make this a for loop.
+  let (tx_market, rx_market): (Sender<MarketMessages>, Receiver<MarketMessages>) = channel();
+  let history = SharedHistory::new(history);
+
+  let tx_market_clone = tx_market.clone();
+  let market_history = history.clone();
+  let stock_count = scenario.stock_count();
+  let fee_bps = scenario.fee_bps;
+  let market_handle = thread::spawn(move || {
+    market::start_market(0, tx_market_clone, rx_market, stock_count, fee_bps, market_history, event_log)});
+
+  let mut markets = HashMap::new();
+  markets.insert(0, tx_market.clone());
+
+  let mut actors_with_timers = vec![];
+  let mut actor_handles = vec![];
+  let mut endowments = vec![];
+
+  let mut current_id = 0;
+  //TODO make more stocks and add history so actors can query on it.
+  let money = scenario.starting_money;
+  for _ in 0..scenario.standard_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Standard Actor", m.clone(), move || {start_actor(current_id, m, money, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Standard Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for id in 0..scenario.corporate_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let quantity = scenario.corporate_starting_quantity;
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Corporate Actor", m.clone(), move || {start_corporate_actor(current_id, m, money, id, quantity, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Corporate Actor", scenario.starting_money).with_stock(id, quantity));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.scripted_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let script = scenario.script.clone();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Scripted Actor", m.clone(), move || {start_scripted_actor(current_id, m, money, script, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Scripted Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.smarter_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Smarter Actor", m.clone(), move || {start_smarter_actor(current_id, m, money, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Smarter Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.random_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let seed = actor_seed(scenario.seed, current_id);
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Random Actor", m.clone(), move || {start_random_actor(current_id, m, money, seed, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Random Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.dummy_actor_1s {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Dumb Actor 1", m.clone(), move || {start_dummy_actor_1(current_id, m, money, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Dumb Actor 1", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.dummy_actor_2s {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Dumb Actor 2", m.clone(), move || {start_dummy_actor_2(current_id, m, money, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Dumb Actor 2", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.market_makers {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let config = scenario.market_maker.clone();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Market Maker", m.clone(), move || {start_market_maker(current_id, m, money, config, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Market Maker", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.momentum_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let config = scenario.momentum.clone();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Momentum Actor", m.clone(), move || {start_momentum_actor(current_id, m, money, config, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Momentum Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.mean_reversion_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let config = scenario.mean_reversion.clone();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Mean Reversion Actor", m.clone(), move || {start_mean_reversion_actor(current_id, m, money, config, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Mean Reversion Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  for _ in 0..scenario.informed_actors {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let (config, seed) = (scenario.informed.clone(), actor_seed(scenario.seed, current_id));
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, "Informed Actor", m.clone(), move || {start_informed_actor(current_id, m, money, config, seed, actor_tx, actor_rx);})})));
+    endowments.push(Endowment::new(current_id, "Informed Actor", scenario.starting_money));
+    current_id += 1;
+  }
+
+  if let Some(ref source) = scenario.strategy {
+    for _ in 0..scenario.strategy_actors {
+      let m = markets.clone();
+      let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+      let source = source.clone();
+      actors_with_timers.push(actor_tx.clone());
+      actor_handles.push((current_id, thread::spawn(move || {
+        supervise(current_id, "Strategy Actor", m.clone(), move || {start_strategy_actor(current_id, m, money, source, actor_tx, actor_rx);})})));
+      endowments.push(Endowment::new(current_id, "Strategy Actor", scenario.starting_money));
+      current_id += 1;
+    }
+  }
+
+  let zi_traders = draw_traders(&scenario.zi, scenario.seed, current_id);
+  for trader in zi_traders.iter() {
+    let m = markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    let (config, seed, kind) = (scenario.zi.clone(), actor_seed(scenario.seed, current_id), trader.kind());
+    let endowment = Endowment::new(current_id, kind, scenario.starting_money);
+    endowments.push(if trader.buyer {endowment} else {endowment.with_stock(config.stock_id, trader.units.len())});
+    let trader = trader.clone();
+    actors_with_timers.push(actor_tx.clone());
+    actor_handles.push((current_id, thread::spawn(move || {
+      supervise(current_id, kind, m.clone(), move || {start_zi_trader(trader, m, money, config, seed, actor_tx, actor_rx);})})));
+    current_id += 1;
+  }
+
+  let mut pool = ActorPool::new(scenario.workers, scenario.pool_round);
+  for _ in 0..scenario.pooled_random_actors {
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    actors_with_timers.push(actor_tx.clone());
+    let actor = PooledRandomActor::new(current_id, markets.clone(), money, actor_seed(scenario.seed, current_id), &actor_tx);
+    pool.spawn(Box::new(actor), actor_rx, markets.clone());
+    endowments.push(Endowment::new(current_id, "Random Actor", scenario.starting_money));
+    current_id += 1;
+  }
+  if scenario.pooled_random_actors > 0 {
+    println!("Started {} pooled Random Actors", scenario.pooled_random_actors);
+  }
+
+  Session {markets,
+           tx_market,
+           market_handle,
+           history,
+           actors_with_timers,
+           actor_handles,
+           pool,
+           endowments,
+           zi_traders,
+           next_id: current_id,
+           tick: scenario.tick,
+           ticks: scenario.ticks}
+}
+
+impl Session {
+  //Starts one more actor on a thread of its own and returns its id.
+  pub fn spawn<F>(&mut self, kind: &'static str, starting_money: usize, strategy: F) -> usize
+    where F: FnOnce(usize, HashMap<usize, Sender<MarketMessages>>, Sender<ActorMessages>, Receiver<ActorMessages>) + Send + 'static {
+    let id = self.next_id;
+    let m = self.markets.clone();
+    let (actor_tx, actor_rx): (Sender<ActorMessages>, Receiver<ActorMessages>) = channel();
+    self.actors_with_timers.push(actor_tx.clone());
+    self.actor_handles.push((id, thread::spawn(move || {
+      supervise(id, kind, m.clone(), move || {strategy(id, m, actor_tx, actor_rx);})})));
+    self.endowments.push(Endowment::new(id, kind, starting_money));
+    self.next_id += 1;
+    id
+  }
+
+  pub fn history(&self) -> Arc<MarketHistory> {
+    self.history.snapshot()
+  }
+
+  //Tick t of the run: tells everyone the time and gives them a tick to act on it.
+  pub fn tick(&self, t: usize) {
+    let tick = self.tick;
+    let last_tick = self.ticks.saturating_sub(1);
+    for tx in self.actors_with_timers.iter() {
+      let _ = tx.send(ActorMessages::Time(t * tick, last_tick * tick));
+    }
+    for (_, market_tx) in self.markets.iter() {
+      let _ = market_tx.send(MarketMessages::Time(t * tick, last_tick * tick));
+    }
+    thread::sleep(Duration::from_millis(tick as u64));
+  }
+
+  //Closes the market, stops every actor and collects what they ended with.
+  pub fn finish(self) -> RunResult {
+    let Session {markets, tx_market, market_handle, history, actors_with_timers, actor_handles, pool, endowments, zi_traders, ..} = self;
+    //Close the market first so unsettled transactions are rolled back before the actors report.
+    let (closed_tx, closed_rx): (Sender<usize>, Receiver<usize>) = channel();
+    let mut closing = 0;
+    for (_, market_tx) in markets.iter() {
+      //A market that already died can't be closed, it is reported when joined below.
+      if market_tx.send(MarketMessages::Shutdown(closed_tx.clone())).is_ok() {
+        closing += 1;
+      }
+    }
+    drop(closed_tx);
+    //One dying while it closes drops its reply channel, which ends the wait.
+    for _ in 0..closing {
+      if closed_rx.recv().is_err() {
+        break;
+      }
+    }
+    //The market thread exits once the actors have dropped their senders as well.
+    drop(markets);
+    drop(tx_market);
+
+    let (my_tx, my_rx) = channel::<(usize, String)>();
+    for tx in actors_with_timers.iter() {
+      //An actor that already died can't be stopped, it is reported when joined below.
+      let _ = tx.send(ActorMessages::Stop(my_tx.clone()));
+    }
+    drop(my_tx);
+
+    let mut responses: Vec<String> = Vec::with_capacity(actors_with_timers.len());
+    for _ in 0..actors_with_timers.len() {
+      responses.push("".to_string());
+    }
+
+    while let Ok((id, status)) = my_rx.recv() {
+      responses[id] = status;
+    }
+
+    let mut failed = vec![];
+    for (id, handle) in actor_handles {
+      match handle.join() {
+        Ok(Some(crash)) => {responses[id] = crash.status();},
+        Ok(None) => {},
+        Err(_) => {failed.push(format!("Actor {}", id));}
+      }
+    }
+    for crash in pool.join() {
+      responses[crash.actor_id] = crash.status();
+    }
+    let market_stats = match market_handle.join() {
+      Ok(stats) => stats,
+      Err(_) => {
+        failed.push("Market 0".to_string());
+        MarketStats::default()
+      }
+    };
+
+    RunResult {history: history.snapshot(), endowments, responses, failed, market_stats, zi_traders}
+  }
+}
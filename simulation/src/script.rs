@@ -0,0 +1,374 @@
+use std::fs;
+use std::io;
+
+use crate::actor::Actor;
+use crate::messages::MarketHistory;
+
+/*
+A small language for Scripted Actors, one rule per line:
+
+  at 5000 buy 10 of stock 2 at 40
+  every 2000 buy 1 of 0 at 25
+  if last price of 1 < 20 sell all of 1 at 15
+  when held 3 >= 10 and last 3 > 50 sell 5 of 3 at 50
+  at 20000 cancel all
+
+A rule is a trigger followed by an action.
+  at <time>          once, at the first tick at or after time (milliseconds of the run, t=5000 works too)
+  every <time>       at the first tick of every period of that length
+  if <conditions>    every tick the conditions hold
+  when <conditions>  once each time the conditions start to hold
+Conditions compare a value with a number using <, <=, >, >=, == or !=, joined by and.
+The values are last <stock> (price of one share in its last trade), held <stock>, cash, time
+and fundamental <stock>. A condition on a value that isn't known yet, like the last price of a
+stock that hasn't traded, doesn't hold.
+The actions are
+  buy <shares> of <stock> [at <price>]
+  sell <shares or all> [of <stock>] [at <price>]
+  cancel <stock or all>
+Prices are per share. Without at <price> an order goes at the last price of one share, rounded
+up for a buy and down for a sale, and isn't made before the stock has traded. sell all without
+a stock sells everything held. An actor has one order per stock resting at a time (see
+trading.rs), a new one replaces it and one that is already resting is left in place.
+Blank lines and everything after a # are ignored, as are the words stock, price, limit and shares.
+*/
+
+#[derive(Clone, Debug)]
+pub struct Script {
+  pub rules: Vec<Rule>
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+  pub line: usize,
+  pub trigger: Trigger,
+  pub action: Action
+}
+
+#[derive(Clone, Debug)]
+pub enum Trigger {
+  At(usize),
+  Every(usize),
+  If(Vec<Condition>),
+  When(Vec<Condition>)
+}
+
+#[derive(Clone, Debug)]
+pub struct Condition {
+  pub value: Value,
+  pub op: Op,
+  pub number: f64
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Value {
+  Last(usize),
+  Held(usize),
+  Cash,
+  Time,
+  Fundamental(usize)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+  Equal,
+  NotEqual
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shares {
+  Count(usize),
+  All
+}
+
+#[derive(Clone, Debug)]
+pub enum Action {
+  Buy {shares: usize, stock_id: usize, price: Option<usize>},
+  Sell {shares: Shares, stock_id: Option<usize>, price: Option<usize>}, //No stock sells every stock held
+  Cancel(Option<usize>) //None cancels every stock
+}
+
+impl Value {
+  //None while the value isn't known.
+  pub fn read(&self, actor: &Actor, history: &MarketHistory, now: usize) -> Option<f64> {
+    match self {
+      Value::Last(stock_id) => history.last_unit_price(*stock_id),
+      Value::Held(stock_id) => Some(*actor.stocks.get(stock_id).unwrap_or(&0) as f64),
+      Value::Cash => Some(actor.money as f64),
+      Value::Time => Some(now as f64),
+      Value::Fundamental(stock_id) => history.fundamental_value(*stock_id, now).map(|value| value as f64)
+    }
+  }
+}
+
+impl Op {
+  pub fn holds(&self, left: f64, right: f64) -> bool {
+    match self {
+      Op::Less => left < right,
+      Op::LessOrEqual => left <= right,
+      Op::Greater => left > right,
+      Op::GreaterOrEqual => left >= right,
+      Op::Equal => left == right,
+      Op::NotEqual => left != right
+    }
+  }
+}
+
+pub fn conditions_hold(conditions: &[Condition], actor: &Actor, history: &MarketHistory, now: usize) -> bool {
+  conditions.iter().all(|condition| match condition.value.read(actor, history, now) {
+    Some(value) => condition.op.holds(value, condition.number),
+    None => false
+  })
+}
+
+pub fn load_script(path: &str) -> io::Result<Script> {
+  let text = fs::read_to_string(path)?;
+  parse_script(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+//Parses a whole script, the error names the first line that couldn't be read.
+pub fn parse_script(text: &str) -> Result<Script, String> {
+  let mut rules = vec![];
+  for (i, line) in text.lines().enumerate() {
+    let code = line.split('#').next().unwrap_or("");
+    let words: Vec<String> = code.split_whitespace()
+      .map(|word| word.to_lowercase())
+      .filter(|word| !["stock", "price", "limit", "shares"].contains(&word.as_str()))
+      .collect();
+    if words.is_empty() {
+      continue;
+    }
+    let mut parser = Parser {words: &words, position: 0};
+    match parser.rule() {
+      Ok((trigger, action)) => {rules.push(Rule {line: i + 1, trigger, action});},
+      Err(message) => {return Err(format!("line {}: {}", i + 1, message));}
+    }
+  }
+  Ok(Script {rules})
+}
+
+struct Parser<'a> {
+  words: &'a [String],
+  position: usize
+}
+
+impl<'a> Parser<'a> {
+  fn rule(&mut self) -> Result<(Trigger, Action), String> {
+    let trigger = match self.next()?.as_str() {
+      "at" => Trigger::At(self.time()?),
+      "every" => {
+        let period = self.time()?;
+        if period == 0 {
+          return Err("every needs a period above 0".to_string());
+        }
+        Trigger::Every(period)
+      },
+      "if" => Trigger::If(self.conditions()?),
+      "when" => Trigger::When(self.conditions()?),
+      other => {return Err(format!("expected at, every, if or when, found {}", other));}
+    };
+    let action = self.action()?;
+    match self.words.get(self.position) {
+      Some(extra) => Err(format!("unexpected {} after the action", extra)),
+      None => Ok((trigger, action))
+    }
+  }
+
+  fn action(&mut self) -> Result<Action, String> {
+    match self.next()?.as_str() {
+      "buy" => {
+        let shares = self.number()?;
+        self.expect("of")?;
+        let stock_id = self.number()?;
+        Ok(Action::Buy {shares, stock_id, price: self.price()?})
+      },
+      "sell" => {
+        let shares = if self.peek() == Some("all") {
+          self.position += 1;
+          Shares::All
+        } else {
+          Shares::Count(self.number()?)
+        };
+        let stock_id = match (self.peek(), shares) {
+          (Some("of"), _) => {
+            self.position += 1;
+            Some(self.number()?)
+          },
+          (_, Shares::All) => None,
+          _ => {return Err("sell needs a stock unless it sells all".to_string());}
+        };
+        Ok(Action::Sell {shares, stock_id, price: self.price()?})
+      },
+      "cancel" => {
+        if self.peek() == Some("all") {
+          self.position += 1;
+          Ok(Action::Cancel(None))
+        } else {
+          Ok(Action::Cancel(Some(self.number()?)))
+        }
+      },
+      other => Err(format!("expected buy, sell or cancel, found {}", other))
+    }
+  }
+
+  fn conditions(&mut self) -> Result<Vec<Condition>, String> {
+    let mut conditions = vec![self.condition()?];
+    while self.peek() == Some("and") {
+      self.position += 1;
+      conditions.push(self.condition()?);
+    }
+    Ok(conditions)
+  }
+
+  fn condition(&mut self) -> Result<Condition, String> {
+    let value = match self.next()?.as_str() {
+      "last" => {
+        if self.peek() == Some("of") {
+          self.position += 1;
+        }
+        Value::Last(self.number()?)
+      },
+      "held" => Value::Held(self.number()?),
+      "fundamental" => Value::Fundamental(self.number()?),
+      "cash" => Value::Cash,
+      "time" => Value::Time,
+      other => {return Err(format!("expected last, held, fundamental, cash or time, found {}", other));}
+    };
+    let op = match self.next()?.as_str() {
+      "<" => Op::Less,
+      "<=" => Op::LessOrEqual,
+      ">" => Op::Greater,
+      ">=" => Op::GreaterOrEqual,
+      "==" | "=" => Op::Equal,
+      "!=" => Op::NotEqual,
+      other => {return Err(format!("expected a comparison, found {}", other));}
+    };
+    let word = self.next()?;
+    match word.parse::<f64>() {
+      Ok(number) => Ok(Condition {value, op, number}),
+      Err(_) => Err(format!("expected a number, found {}", word))
+    }
+  }
+
+  //An optional at <price>, without one the order goes at the last price.
+  fn price(&mut self) -> Result<Option<usize>, String> {
+    if self.peek() == Some("at") {
+      self.position += 1;
+      Ok(Some(self.number()?))
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn time(&mut self) -> Result<usize, String> {
+    let word = self.next()?;
+    let digits = word.strip_prefix("t=").unwrap_or(&word);
+    digits.parse().map_err(|_| format!("expected a time in milliseconds, found {}", word))
+  }
+
+  fn number(&mut self) -> Result<usize, String> {
+    let word = self.next()?;
+    word.parse().map_err(|_| format!("expected a whole number, found {}", word))
+  }
+
+  fn expect(&mut self, keyword: &str) -> Result<(), String> {
+    let word = self.next()?;
+    if word == keyword {Ok(())} else {Err(format!("expected {}, found {}", keyword, word))}
+  }
+
+  fn peek(&self) -> Option<&str> {
+    self.words.get(self.position).map(|word| word.as_str())
+  }
+
+  fn next(&mut self) -> Result<String, String> {
+    match self.words.get(self.position) {
+      Some(word) => {
+        self.position += 1;
+        Ok(word.clone())
+      },
+      None => Err("line ends too early".to_string())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(line: &str) -> Rule {
+    let mut script = parse_script(line).unwrap();
+    assert_eq!(script.rules.len(), 1);
+    script.rules.remove(0)
+  }
+
+  fn error(text: &str) -> String {
+    match parse_script(text) {
+      Ok(script) => panic!("parsed {:?}", script),
+      Err(message) => message
+    }
+  }
+
+  #[test]
+  fn triggers() {
+    assert!(matches!(rule("at 5000 cancel all").trigger, Trigger::At(5000)));
+    assert!(matches!(rule("at t=250 cancel all").trigger, Trigger::At(250)));
+    assert!(matches!(rule("every 2000 cancel 1").trigger, Trigger::Every(2000)));
+    match rule("if last of 1 < 20 and cash >= 100.5 cancel 1").trigger {
+      Trigger::If(conditions) => {
+        assert_eq!(conditions.len(), 2);
+        assert!(matches!(conditions[0], Condition {value: Value::Last(1), op: Op::Less, number} if number == 20.0));
+        assert!(matches!(conditions[1], Condition {value: Value::Cash, op: Op::GreaterOrEqual, number} if number == 100.5));
+      },
+      other => panic!("{:?}", other)
+    }
+    match rule("when held 3 != 0 and time > 10 and fundamental 2 == 40 cancel all").trigger {
+      Trigger::When(conditions) => {
+        assert!(matches!(conditions[0], Condition {value: Value::Held(3), op: Op::NotEqual, ..}));
+        assert!(matches!(conditions[1], Condition {value: Value::Time, op: Op::Greater, ..}));
+        assert!(matches!(conditions[2], Condition {value: Value::Fundamental(2), op: Op::Equal, ..}));
+      },
+      other => panic!("{:?}", other)
+    }
+  }
+
+  #[test]
+  fn actions() {
+    assert!(matches!(rule("at 0 buy 10 of 2 at 40").action, Action::Buy {shares: 10, stock_id: 2, price: Some(40)}));
+    assert!(matches!(rule("at 0 buy 1 of 0").action, Action::Buy {shares: 1, stock_id: 0, price: None}));
+    assert!(matches!(rule("at 0 sell 5 of 3 at 50").action, Action::Sell {shares: Shares::Count(5), stock_id: Some(3), price: Some(50)}));
+    assert!(matches!(rule("at 0 sell all of 1 at 15").action, Action::Sell {shares: Shares::All, stock_id: Some(1), price: Some(15)}));
+    assert!(matches!(rule("at 0 sell all").action, Action::Sell {shares: Shares::All, stock_id: None, price: None}));
+    assert!(matches!(rule("at 0 sell all at 7").action, Action::Sell {shares: Shares::All, stock_id: None, price: Some(7)}));
+    assert!(matches!(rule("at 0 cancel 4").action, Action::Cancel(Some(4))));
+    assert!(matches!(rule("at 0 cancel all").action, Action::Cancel(None)));
+  }
+
+  #[test]
+  fn filler_words_comments_and_case() {
+    assert!(matches!(rule("AT 100 Buy 10 shares of stock 2 at price 40").action, Action::Buy {shares: 10, stock_id: 2, price: Some(40)}));
+    assert!(matches!(rule("if last price of stock 1 < 20 sell all of stock 1 at limit 15 # get out").action,
+                     Action::Sell {shares: Shares::All, stock_id: Some(1), price: Some(15)}));
+    let script = parse_script("# a comment\n\nat 1 cancel all\n   \nevery 5 cancel 0 # trailing\n").unwrap();
+    let lines: Vec<usize> = script.rules.iter().map(|rule| rule.line).collect();
+    assert_eq!(lines, vec![3, 5]);
+  }
+
+  #[test]
+  fn errors_name_the_line() {
+    assert_eq!(error("at 1 cancel all\nsometime buy 1 of 0"), "line 2: expected at, every, if or when, found sometime");
+    assert_eq!(error("every 0 cancel all"), "line 1: every needs a period above 0");
+    assert_eq!(error("at soon cancel all"), "line 1: expected a time in milliseconds, found soon");
+    assert_eq!(error("\n\nat 1 sell 5"), "line 3: sell needs a stock unless it sells all");
+    assert_eq!(error("at 1 buy 10 2"), "line 1: expected of, found 2");
+    assert_eq!(error("at 1 buy 10 of"), "line 1: line ends too early");
+    assert_eq!(error("at 1 hold 3"), "line 1: expected buy, sell or cancel, found hold");
+    assert_eq!(error("if volume 1 > 3 cancel all"), "line 1: expected last, held, fundamental, cash or time, found volume");
+    assert_eq!(error("if cash ~ 3 cancel all"), "line 1: expected a comparison, found ~");
+    assert_eq!(error("if cash > lots cancel all"), "line 1: expected a number, found lots");
+    assert_eq!(error("at 1 cancel all now"), "line 1: unexpected now after the action");
+  }
+}
@@ -1,28 +1,40 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 use std::cmp::max;
 
-use messages::{MarketMessages, MarketHistory, ActorMessages, TransactionRequest};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
-use actor::Actor;
-use actor::{add_stock, remove_stock, status};
+use crate::messages::{MarketMessages, SharedHistory, ActorMessages, TransactionRequest};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{BuyRequest, Commit, Cancel, RegisterActor, SellRequest};
+use crate::actor::Actor;
+use crate::actor::{add_stock, remove_stock, status};
+use crate::actor::{answer_settlement, has_pending_transaction};
+use crate::messages::MarketHistory;
+use crate::script::{conditions_hold, Action, Script, Shares, Trigger};
+use crate::trading::RestingOrders;
 
-pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+//Follows script when one was loaded (see script.rs), otherwise the built in one below.
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_match, clippy::needless_late_init, clippy::redundant_field_names, clippy::single_match)]
+pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, script: Option<Arc<Script>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  match script {
+    Some(script) => {
+      run_script(actor_id, existing_markets, starting_money, script, actor_tx, actor_rx);
+      return;
+    },
+    None => {}
+  }
   println!("Starting Scripted Actor {}", actor_id);
-  let mut stop_flag = false;
   let mut init_history = false;
   let mut actor = Actor { id: actor_id,
-                          money: 100,
+                          money: starting_money,
                           stocks: HashMap::new(),
                           pending_money: 0,
                           pending_stock: (0, 0),
                           markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))};
+                          history: SharedHistory::default()};
 
   let mut current_time: usize = 0;
   let mut max_time: usize = 0;
@@ -37,22 +49,17 @@ pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Se
   }
 
   loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
-
     if init_history {
       let local_stocks;
       {
-        local_stocks = actor.history.lock().unwrap().stocks.clone();
+        local_stocks = actor.history.snapshot().stocks.clone();
       }
       if current_time < max_time / 2 {
         for stock in local_stocks.iter() {
           match actor.stocks.get(stock) {
             Some(count) => {
                 for (_, market_tx) in actor.markets.iter() {
-                  let t = TransactionRequest{actor_id: actor.id, transaction_id: 0, stock_id: *stock, price: max(100 - low_bid, 1), quantity: *count};
+                  let t = TransactionRequest{actor_id: actor.id, transaction_id: 0, stock_id: *stock, price: max(100usize.saturating_sub(low_bid), 1), quantity: *count};
                   market_tx.send(SellRequest(t)).unwrap();
                 }
               },
@@ -68,7 +75,7 @@ pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Se
       else if current_time < 3 * max_time / 4 {
         for (stock, count) in actor.stocks.iter() {
           for (_, market_tx) in actor.markets.iter() {
-            let t = TransactionRequest{actor_id: actor.id, transaction_id: 0, stock_id: *stock, price: max(100 - low_bid, 1), quantity: *count};
+            let t = TransactionRequest{actor_id: actor.id, transaction_id: 0, stock_id: *stock, price: max(100usize.saturating_sub(low_bid), 1), quantity: *count};
             market_tx.send(SellRequest(t)).unwrap();
           }
         }
@@ -88,7 +95,7 @@ pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Se
       low_bid += 4;
     }
     else if current_time % 1000 == 0 {
-      low_bid -= 3;
+      low_bid = low_bid.saturating_sub(3);
     }
 
 
@@ -212,17 +219,112 @@ pub fn start_scripted_actor(actor_id: usize, existing_markets: HashMap<usize, Se
             },
             ReceiveActivityCount(_, _, _) => {},
             Stop(main_channel) => {
-              main_channel.send((actor.id, "(Scripted Actor) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+              main_channel.send((actor.id, "(Scripted Actor) ".to_string() + status(&actor).as_str())).unwrap();
+              return;
             }
           }
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(10));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id);}
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(10));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
     }
   }
 }
 
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
+#[derive(Clone, Default)]
+struct RuleState {
+  fired: bool, //An at rule that already ran
+  period: Option<usize>, //The last period an every rule ran in
+  held: bool //Whether a when rule's conditions held on the last tick
+}
+
+fn run_script(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, script: Arc<Script>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Scripted Actor {} with {} rules", actor_id, script.rules.len());
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut orders = RestingOrders::default();
+  let mut states = vec![RuleState::default(); script.rules.len()];
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(now, _)) => {follow(&actor, &script, now, &mut states, &mut orders);},
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Scripted Actor) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+//Runs the rules whose triggers fire at now, in the order they are written.
+fn follow(actor: &Actor, script: &Script, now: usize, states: &mut [RuleState], orders: &mut RestingOrders) {
+  //Holdings are off while a settlement is under way, a rule waits for the next tick.
+  if has_pending_transaction(actor) {
+    return;
+  }
+  let history = actor.history.snapshot();
+  for (rule, state) in script.rules.iter().zip(states.iter_mut()) {
+    let fire = match &rule.trigger {
+      Trigger::At(time) => !state.fired && now >= *time,
+      Trigger::Every(period) => state.period != Some(now / period),
+      Trigger::If(conditions) => conditions_hold(conditions, actor, &history, now),
+      Trigger::When(conditions) => {
+        let holds = conditions_hold(conditions, actor, &history, now);
+        let started = holds && !state.held;
+        state.held = holds;
+        started
+      }
+    };
+    if !fire {
+      continue;
+    }
+    state.fired = true;
+    if let Trigger::Every(period) = rule.trigger {state.period = Some(now / period);}
+    perform(actor, &history, &rule.action, orders);
+  }
+}
+
+fn perform(actor: &Actor, history: &MarketHistory, action: &Action, orders: &mut RestingOrders) {
+  match action {
+    Action::Buy {shares, stock_id, price} => {
+      if let Some(price) = price.or_else(|| history.last_unit_price(*stock_id).map(|last| last.ceil() as usize)) {
+        orders.buy(actor, *stock_id, price, *shares);
+      }
+    },
+    Action::Sell {shares, stock_id, price} => {
+      let stock_ids: Vec<usize> = match stock_id {
+        Some(stock_id) => vec![*stock_id],
+        None => actor.stocks.iter().filter(|(_, held)| **held > 0).map(|(stock_id, _)| *stock_id).collect()
+      };
+      for stock_id in stock_ids {
+        let held = *actor.stocks.get(&stock_id).unwrap_or(&0);
+        let count = match shares {
+          Shares::All => held,
+          Shares::Count(count) => (*count).min(held)
+        };
+        if let Some(price) = price.or_else(|| history.last_unit_price(stock_id).map(|last| (last.floor() as usize).max(1))) {
+          orders.sell(actor, stock_id, price, count);
+        }
+      }
+    },
+    Action::Cancel(Some(stock_id)) => {orders.revoke(actor, *stock_id);},
+    Action::Cancel(None) => {orders.revoke_all(actor);}
+  }
 }
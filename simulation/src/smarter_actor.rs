@@ -1,32 +1,31 @@
 use std::collections::HashMap;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
-use std::old_io::timer;
+use std::thread;
 use std::time::Duration;
 use std::cmp::max;
 
-use messages::{ActorMessages, TransactionRequest, MarketMessages, MarketHistory};
-use messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
-use messages::MarketMessages::{BuyRequest, SellRequest, Commit, Cancel, RegisterActor};
-use actor::Actor;
-use actor::{add_stock, remove_stock, status};
+use crate::messages::{ActorMessages, TransactionRequest, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{StockRequest, MoneyRequest, CommitTransaction, AbortTransaction, History, Time, ReceiveActivityCount, Stop};
+use crate::messages::MarketMessages::{BuyRequest, SellRequest, Commit, Cancel, RegisterActor};
+use crate::actor::Actor;
+use crate::actor::{add_stock, has_pending_transaction, remove_stock, status};
 
 // Smarter actor
 // (monitors price last sold at and put a sell request if any stocks are above their purchase price)
 
 
-pub fn start_smarter_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy, clippy::collapsible_match, clippy::needless_late_init, clippy::redundant_field_names, clippy::single_match)]
+pub fn start_smarter_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
   println!("Starting Smarter_Actor {}", actor_id);
-  let mut stop_flag = false;
   let mut init_history = false;
   let mut actor = Actor { id: actor_id,
-                          money: 100,
+                          money: starting_money,
                           stocks: HashMap::new(),
                           pending_money: 0,
                           pending_stock: (0, 0),
                           markets: existing_markets,
-                          history: Arc::new(Mutex::new(MarketHistory {history: HashMap::new(), stocks: vec![]}))
+                          history: SharedHistory::default()
                           };
   // Stocks = HashMap<market_id, HashMap<stock_id, (price,quantity)>>
   let mut buy_requests : HashMap<usize, HashMap<usize,(usize,usize)>> = HashMap::new();
@@ -37,16 +36,12 @@ pub fn start_smarter_actor(actor_id: usize, existing_markets: HashMap<usize, Sen
   }
 
   loop {
-    if stop_flag {
-      timer::sleep(Duration::milliseconds(1000));
-      continue;
-    }
     //Logic
     let mark_clone = actor.markets.clone();
     let stock_clone = actor.stocks.clone();
 
     if init_history {
-      let hist = actor.history.lock().unwrap();
+      let hist = actor.history.snapshot();
 
       // For each stock in history
       for stock in hist.stocks.iter(){
@@ -208,17 +203,18 @@ pub fn start_smarter_actor(actor_id: usize, existing_markets: HashMap<usize, Sen
             Time(_, _) => {},
             ReceiveActivityCount(_,_,_) => {},
             Stop(main_channel) => {
-              main_channel.send((actor.id, "(Smarter Actor) ".to_string() + status(&actor).as_slice())).unwrap();
-              stop_flag = true;
+              main_channel.send((actor.id, "(Smarter Actor) ".to_string() + status(&actor).as_str())).unwrap();
+              return;
             }
           }
         },
-      Err(TryRecvError::Empty) => {timer::sleep(Duration::milliseconds(10));},
-      Err(TryRecvError::Disconnected) => {println!("ERROR: Smarter_actor {} disconnected", actor.id);}
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(10));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Smarter_actor {} disconnected", actor.id); return;}
     }
   }
 }
 
+#[allow(clippy::single_match)]
 fn send_message(market_id : usize, markets: &HashMap<usize, Sender<MarketMessages>>, message: MarketMessages){
   match markets.get(&market_id){
     Some(market) => {
@@ -227,7 +223,3 @@ fn send_message(market_id : usize, markets: &HashMap<usize, Sender<MarketMessage
     None => {}
   }
 }
-
-fn has_pending_transaction(actor: &Actor) -> bool {
-  (*actor).pending_money > 0 || (*actor).pending_stock.1 > 0
-}
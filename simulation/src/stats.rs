@@ -0,0 +1,52 @@
+/*
+Summary statistics over plain lists of values, shared by the strategies and the reports.
+An empty list has a mean and median of 0 and fewer than two values have no deviation.
+*/
+
+pub fn mean(values: &[f64]) -> f64 {
+  if values.is_empty() {0.0} else {values.iter().sum::<f64>() / values.len() as f64}
+}
+
+pub fn median(values: &[f64]) -> f64 {
+  if values.is_empty() {
+    return 0.0;
+  }
+  let mut sorted = values.to_vec();
+  sorted.sort_by(|a, b| a.total_cmp(b));
+  let middle = sorted.len() / 2;
+  if sorted.len() % 2 == 0 {(sorted[middle - 1] + sorted[middle]) / 2.0} else {sorted[middle]}
+}
+
+//Sample standard deviation.
+pub fn stddev(values: &[f64]) -> f64 {
+  if values.len() < 2 {
+    return 0.0;
+  }
+  let m = mean(values);
+  (values.iter().map(|v| (v - m) * (v - m)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+  }
+
+  #[test]
+  fn median_of_odd_and_even_counts() {
+    assert_close(median(&[]), 0.0);
+    assert_close(median(&[3.0]), 3.0);
+    assert_close(median(&[5.0, -1.0, 3.0]), 3.0);
+    assert_close(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+    assert_close(median(&[7.0, 7.0, 1.0, 100.0]), 7.0);
+  }
+
+  #[test]
+  fn sample_standard_deviation() {
+    assert_close(stddev(&[]), 0.0);
+    assert_close(stddev(&[3.0]), 0.0);
+    assert_close(stddev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), (32.0f64 / 7.0).sqrt());
+  }
+}
@@ -0,0 +1,322 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST, CallFnOptions, FLOAT, INT};
+
+use crate::messages::{ActorMessages, MarketHistory, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{AbortTransaction, CommitTransaction, History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::trading::RestingOrders;
+
+/*
+Runs a strategy written in Rhai (https://rhai.rs), loaded with --strategy, so a strategy can
+change without rebuilding the crate. The script gets the callbacks a native actor handles,
+each one optional:
+
+  fn on_start() {}                            once, before the first tick
+  fn on_tick(now, end) {}                     every Time message, milliseconds into the run and its last tick
+  fn on_fill(stock, shares, price, bought) {} a settlement went through, price covers all the shares
+  fn on_abort() {}                            a settlement was called off, the money or stock is back
+
+Settlement itself is handled for it as for every other actor. Inside a callback this is an
+object map kept for the whole run, the place for the strategy's own state. Top level statements
+run once when the actor starts.
+
+It can look at
+  cash(), held(stock), settling(), now(), stocks()
+  last_price(stock)         price of one share in the last trade, () before the stock trades
+  prices(stock, count)      prices of one share in the last count trades, oldest first
+  candles(stock, count)     the last count candles, oldest first, maps of time, open, high, low,
+                            close and volume in the prices trades were recorded at
+  fundamental(stock)        the hidden value (see fundamental.rs), () without one
+and trade with
+  buy(stock, price, shares), sell(stock, price, shares), cancel(stock), cancel_all()
+Prices are per share and orders go through trading::RestingOrders, so each stock has one order
+resting at a time, a new one replaces it and the same order again is left in place. Orders
+are sent once the callback returns. A script error stops the actor like a panic in a native one.
+*/
+
+//Operations one callback may take before it is stopped, so a runaway loop can't hang the run.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+//What the script sees while one of its callbacks runs, and the orders it asks for.
+#[derive(Default)]
+struct Context {
+  now: usize,
+  money: usize,
+  stocks: HashMap<usize, usize>,
+  settling: bool,
+  history: Arc<MarketHistory>,
+  orders: Vec<Order>
+}
+
+enum Order {
+  Buy(usize, usize, usize), //stock_id, price per share, shares
+  Sell(usize, usize, usize), //stock_id, price per share, shares
+  Cancel(usize), //stock_id
+  CancelAll
+}
+
+struct Strategy {
+  engine: Engine,
+  ast: AST,
+  scope: Scope<'static>,
+  state: Dynamic, //Bound to this in every callback
+  context: Rc<RefCell<Context>>
+}
+
+//Reads and compiles a strategy, so errors show up before the run starts.
+pub fn load_strategy(path: &str) -> Result<String, String> {
+  let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+  Engine::new().compile(&source).map_err(|e| format!("{}: {}", path, e))?;
+  Ok(source)
+}
+
+pub fn start_strategy_actor(actor_id: usize, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, source: Arc<String>, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting Strategy Actor {}", actor_id);
+  let mut actor = Actor { id: actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  let mut orders = RestingOrders::default();
+  let mut strategy = match Strategy::new(&source) {
+    Ok(strategy) => strategy,
+    Err(e) => panic!("{}", e)
+  };
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+  strategy.call(&actor, 0, &mut orders, "on_start", ());
+
+  let mut now = 0;
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        orders.record(&actor, &message);
+        //Whether the actor was buying is only known before the settlement is answered.
+        let fill = match &message {
+          CommitTransaction(counterpart) => Some((counterpart.stock_id as INT, counterpart.quantity as INT, counterpart.price as INT, actor.pending_money > 0)),
+          _ => None
+        };
+        let aborted = matches!(message, AbortTransaction);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(time, end)) => {
+            now = time;
+            strategy.call(&actor, now, &mut orders, "on_tick", (time as INT, end as INT));
+          },
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, "(Strategy Actor) ".to_string() + status(&actor).as_str())).unwrap();
+            return;
+          },
+          _ => {}
+        }
+        if let Some(fill) = fill {strategy.call(&actor, now, &mut orders, "on_fill", fill);}
+        if aborted {
+          strategy.call(&actor, now, &mut orders, "on_abort", ());
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: Strategy Actor {} disconnected", actor.id); return;}
+    }
+  }
+}
+
+impl Strategy {
+  fn new(source: &str) -> Result<Strategy, String> {
+    let context = Rc::new(RefCell::new(Context::default()));
+    let engine = engine(&context);
+    let ast = engine.compile(source).map_err(|e| e.to_string())?;
+    let mut scope = Scope::new();
+    engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| e.to_string())?;
+    Ok(Strategy {engine, ast, scope, state: Dynamic::from_map(Map::new()), context})
+  }
+
+  //Runs callback if the script defines it with as many parameters as args has, then sends the orders it made.
+  fn call(&mut self, actor: &Actor, now: usize, orders: &mut RestingOrders, callback: &str, args: impl rhai::FuncArgs) {
+    let mut values = vec![];
+    args.parse(&mut values);
+    if !self.ast.iter_functions().any(|f| f.name == callback && f.params.len() == values.len()) {
+      return;
+    }
+    {
+      let mut context = self.context.borrow_mut();
+      context.now = now;
+      context.money = actor.money;
+      context.stocks = actor.stocks.clone();
+      context.settling = has_pending_transaction(actor);
+      context.history = actor.history.snapshot();
+    }
+    let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+    match self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, callback, values) {
+      Ok(_) => {},
+      Err(e) => panic!("{}: {}", callback, e)
+    }
+    let requested: Vec<Order> = self.context.borrow_mut().orders.drain(..).collect();
+    for order in requested {
+      match order {
+        Order::Buy(stock_id, price, shares) => {orders.buy(actor, stock_id, price, shares);},
+        Order::Sell(stock_id, price, shares) => {orders.sell(actor, stock_id, price, shares);},
+        Order::Cancel(stock_id) => {orders.revoke(actor, stock_id);},
+        Order::CancelAll => {orders.revoke_all(actor);}
+      }
+    }
+  }
+}
+
+//Negative numbers from a script count as 0.
+fn whole(value: INT) -> usize {
+  value.max(0) as usize
+}
+
+fn engine(context: &Rc<RefCell<Context>>) -> Engine {
+  let mut engine = Engine::new();
+  engine.set_max_operations(MAX_OPERATIONS);
+
+  let c = context.clone();
+  engine.register_fn("cash", move || c.borrow().money as INT);
+  let c = context.clone();
+  engine.register_fn("held", move |stock_id: INT| *c.borrow().stocks.get(&whole(stock_id)).unwrap_or(&0) as INT);
+  let c = context.clone();
+  engine.register_fn("settling", move || c.borrow().settling);
+  let c = context.clone();
+  engine.register_fn("now", move || c.borrow().now as INT);
+  let c = context.clone();
+  engine.register_fn("stocks", move || c.borrow().history.stocks.iter().map(|stock_id| Dynamic::from_int(*stock_id as INT)).collect::<Array>());
+  let c = context.clone();
+  engine.register_fn("last_price", move |stock_id: INT| match c.borrow().history.last_unit_price(whole(stock_id)) {
+    Some(price) => Dynamic::from_float(price as FLOAT),
+    None => Dynamic::UNIT
+  });
+  let c = context.clone();
+  engine.register_fn("prices", move |stock_id: INT, count: INT| {
+    c.borrow().history.recent_unit_prices(whole(stock_id), whole(count)).into_iter().map(|price| Dynamic::from_float(price as FLOAT)).collect::<Array>()
+  });
+  let c = context.clone();
+  engine.register_fn("candles", move |stock_id: INT, count: INT| {
+    let context = c.borrow();
+    let mut candles: Array = context.history.candles(whole(stock_id)).iter().rev().take(whole(count)).map(|candle| {
+      let mut map = Map::new();
+      map.insert("time".into(), Dynamic::from_int(candle.start_time as INT));
+      map.insert("open".into(), Dynamic::from_int(candle.open as INT));
+      map.insert("high".into(), Dynamic::from_int(candle.high as INT));
+      map.insert("low".into(), Dynamic::from_int(candle.low as INT));
+      map.insert("close".into(), Dynamic::from_int(candle.close as INT));
+      map.insert("volume".into(), Dynamic::from_int(candle.volume as INT));
+      Dynamic::from_map(map)
+    }).collect();
+    candles.reverse();
+    candles
+  });
+  let c = context.clone();
+  engine.register_fn("fundamental", move |stock_id: INT| {
+    let context = c.borrow();
+    match context.history.fundamental_value(whole(stock_id), context.now) {
+      Some(value) => Dynamic::from_int(value as INT),
+      None => Dynamic::UNIT
+    }
+  });
+
+  let c = context.clone();
+  engine.register_fn("buy", move |stock_id: INT, price: INT, shares: INT| {
+    c.borrow_mut().orders.push(Order::Buy(whole(stock_id), whole(price), whole(shares)));
+  });
+  let c = context.clone();
+  engine.register_fn("sell", move |stock_id: INT, price: INT, shares: INT| {
+    c.borrow_mut().orders.push(Order::Sell(whole(stock_id), whole(price), whole(shares)));
+  });
+  let c = context.clone();
+  engine.register_fn("cancel", move |stock_id: INT| {c.borrow_mut().orders.push(Order::Cancel(whole(stock_id)));});
+  let c = context.clone();
+  engine.register_fn("cancel_all", move || {c.borrow_mut().orders.push(Order::CancelAll);});
+  engine
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::mpsc::channel;
+
+  const SCRIPT: &str = r#"
+    fn on_start() { this.ticks = 0; }
+    fn on_tick(now, end) {
+      this.ticks += 1;
+      if this.ticks == 1 {
+        buy(0, 10, 3);
+        sell(1, 12, 2);
+      }
+      if this.ticks == 2 { cancel(0); }
+      this.unknown = type_of(last_price(0)) == "()" && type_of(fundamental(0)) == "()";
+    }
+    fn on_fill(stock, shares) { this.filled = true; }
+  "#;
+
+  fn test_actor() -> (Actor, Receiver<MarketMessages>) {
+    let (market_tx, market_rx) = channel();
+    let actor = Actor {id: 1, money: 100, stocks: HashMap::new(), pending_money: 0, pending_stock: (0, 0),
+                       markets: HashMap::from([(0, market_tx)]), history: SharedHistory::default()};
+    (actor, market_rx)
+  }
+
+  fn state(strategy: &Strategy, key: &str) -> Option<Dynamic> {
+    strategy.state.clone_cast::<Map>().get(key).cloned()
+  }
+
+  #[test]
+  fn callbacks_trade_through_resting_orders() {
+    let (actor, market_rx) = test_actor();
+    let mut orders = RestingOrders::default();
+    let mut strategy = Strategy::new(SCRIPT).unwrap();
+    strategy.call(&actor, 0, &mut orders, "on_start", ());
+    strategy.call(&actor, 100, &mut orders, "on_tick", (100 as INT, 1000 as INT));
+    let sent: Vec<(bool, usize, usize, usize)> = market_rx.try_iter().map(|message| match message {
+      MarketMessages::BuyRequest(request) => (true, request.stock_id, request.price, request.quantity),
+      MarketMessages::SellRequest(request) => (false, request.stock_id, request.price, request.quantity),
+      _ => panic!("expected only orders")
+    }).collect();
+    assert_eq!(sent, vec![(true, 0, 30, 3), (false, 1, 12, 1), (false, 1, 12, 1)]);
+
+    strategy.call(&actor, 200, &mut orders, "on_tick", (200 as INT, 1000 as INT));
+    let revoked: Vec<usize> = market_rx.try_iter().map(|message| match message {
+      MarketMessages::RevokeRequest(stock_id, _, _) => stock_id,
+      _ => panic!("expected only a revoke")
+    }).collect();
+    assert_eq!(revoked, vec![0]);
+  }
+
+  #[test]
+  fn state_persists_and_unknown_values_are_unit() {
+    let (actor, _market_rx) = test_actor();
+    let mut orders = RestingOrders::default();
+    let mut strategy = Strategy::new(SCRIPT).unwrap();
+    strategy.call(&actor, 0, &mut orders, "on_start", ());
+    strategy.call(&actor, 100, &mut orders, "on_tick", (100 as INT, 1000 as INT));
+    strategy.call(&actor, 200, &mut orders, "on_tick", (200 as INT, 1000 as INT));
+    assert_eq!(state(&strategy, "ticks").and_then(|ticks| ticks.as_int().ok()), Some(2));
+    assert_eq!(state(&strategy, "unknown").and_then(|unknown| unknown.as_bool().ok()), Some(true));
+  }
+
+  #[test]
+  fn callbacks_with_other_parameter_counts_are_skipped() {
+    let (actor, _market_rx) = test_actor();
+    let mut orders = RestingOrders::default();
+    let mut strategy = Strategy::new(SCRIPT).unwrap();
+    strategy.call(&actor, 0, &mut orders, "on_fill", (0 as INT, 1 as INT, 10 as INT, true));
+    assert!(state(&strategy, "filled").is_none());
+    strategy.call(&actor, 0, &mut orders, "on_fill", (0 as INT, 1 as INT));
+    assert!(state(&strategy, "filled").is_some());
+  }
+}
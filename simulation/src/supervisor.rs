@@ -0,0 +1,72 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::Sender;
+
+use crate::messages::MarketMessages;
+use crate::messages::MarketMessages::ActorCrashed;
+
+/*
+Runs an actor's strategy and contains any panic inside the actor's own thread.
+When a strategy panics every market is told to revoke its open requests and abort
+whatever it was settling, and the holdings it had at the time are kept for the report.
+*/
+
+thread_local! {
+  //Filled in by the Actor's Drop impl while the strategy unwinds.
+  static FROZEN_STATUS: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub struct CrashReport {
+  pub actor_id: usize,
+  pub kind: String,
+  pub message: String,
+  pub frozen_status: Option<String>
+}
+
+impl CrashReport {
+  pub fn status(&self) -> String {
+    let holdings = match self.frozen_status {
+      Some(ref status) => status.clone(),
+      None => format!("ID: {}, no holdings recorded", self.actor_id)
+    };
+    format!("({}) CRASHED \"{}\" {}", self.kind, self.message, holdings)
+  }
+}
+
+//Called on the actor's thread, returns a report if the strategy panicked.
+pub fn supervise<F: FnOnce()>(actor_id: usize, kind: &str, markets: HashMap<usize, Sender<MarketMessages>>, strategy: F) -> Option<CrashReport> {
+  contain(actor_id, kind, &markets, strategy).err()
+}
+
+//Runs one step of a strategy, e.g. for an actor in an ActorPool, and returns its result unless it panicked.
+pub fn contain<R, F: FnOnce() -> R>(actor_id: usize, kind: &str, markets: &HashMap<usize, Sender<MarketMessages>>, step: F) -> Result<R, CrashReport> {
+  match panic::catch_unwind(AssertUnwindSafe(step)) {
+    Ok(result) => Ok(result),
+    Err(payload) => {
+      for (_, market_tx) in markets.iter() {
+        //A market that is already gone has nothing left to clean up.
+        let _ = market_tx.send(ActorCrashed(actor_id));
+      }
+      Err(CrashReport {actor_id,
+                       kind: kind.to_string(),
+                       message: panic_message(&*payload),
+                       frozen_status: FROZEN_STATUS.with(|status| status.borrow_mut().take())})
+    }
+  }
+}
+
+pub fn freeze_status(status: String) {
+  FROZEN_STATUS.with(|frozen| *frozen.borrow_mut() = Some(status));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  match payload.downcast_ref::<&str>() {
+    Some(message) => message.to_string(),
+    None => match payload.downcast_ref::<String>() {
+      Some(message) => message.clone(),
+      None => "unknown panic".to_string()
+    }
+  }
+}
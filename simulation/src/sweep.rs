@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+use crate::stats::mean;
+use crate::market_stats::{unit_prices, volatility};
+use crate::messages::{MarketHistory, Trade};
+use crate::portfolio::{run_trades, value_portfolios, Valuation};
+use crate::scenario::{run_scenario, Scenario, ACTOR_KINDS};
+
+/*
+Runs a scenario for every combination of a grid of parameters and writes one CSV row per run.
+Each axis lists the values to try, an axis left out keeps the scenario's value.
+Run i of every configuration uses seed + i, so configurations are compared over the same draws.
+Rows are written as the runs finish, a sweep that is stopped early keeps what it has.
+
+Prices are unit prices (a trade's price divided by its quantity). final_price and volatility
+are averaged over the stocks that traded, volatility being the standard deviation of the
+trade to trade log returns. The return columns hold each actor type's mean return in percent.
+*/
+
+pub struct SweepGrid {
+  pub starting_money: Vec<usize>,
+  pub corporate_starting_quantity: Vec<usize>,
+  pub random_actors: Vec<usize>,
+  pub tick: Vec<usize>,
+  pub fee_bps: Vec<usize>
+}
+
+impl SweepGrid {
+  //Every combination, the last axis changing fastest.
+  pub fn scenarios(&self, base: &Scenario) -> Vec<Scenario> {
+    let mut scenarios = vec![base.clone()];
+    scenarios = expand(scenarios, &self.starting_money, |s, v| s.starting_money = v);
+    scenarios = expand(scenarios, &self.corporate_starting_quantity, |s, v| s.corporate_starting_quantity = v);
+    scenarios = expand(scenarios, &self.random_actors, |s, v| s.random_actors = v);
+    scenarios = expand(scenarios, &self.tick, |s, v| s.tick = v);
+    scenarios = expand(scenarios, &self.fee_bps, |s, v| s.fee_bps = v);
+    scenarios
+  }
+}
+
+fn expand<F: Fn(&mut Scenario, usize)>(scenarios: Vec<Scenario>, values: &[usize], set: F) -> Vec<Scenario> {
+  if values.is_empty() {
+    return scenarios;
+  }
+  let mut expanded = vec![];
+  for scenario in scenarios.iter() {
+    for value in values.iter() {
+      let mut next = scenario.clone();
+      set(&mut next, *value);
+      expanded.push(next);
+    }
+  }
+  expanded
+}
+
+//Runs every configuration runs times, on the same seeds counting up from the base scenario's. Returns the number of rows.
+pub fn run_sweep(grid: &SweepGrid, base: &Scenario, runs: usize, history: &MarketHistory, path: &str) -> io::Result<usize> {
+  let scenarios = grid.scenarios(base);
+  let mut writer = BufWriter::new(File::create(path)?);
+  let returns: Vec<String> = ACTOR_KINDS.iter().map(|kind| format!("return_{}", kind.to_lowercase().replace(' ', "_"))).collect();
+  writeln!(writer, "config,seed,starting_money,corporate_starting_quantity,random_actors,tick,fee_bps,\
+trades,volume,turnover,fees,final_price,volatility,{}", returns.join(","))?;
+
+  let mut rows = 0;
+  for (config, scenario) in scenarios.iter().enumerate() {
+    for run in 0..runs {
+      let seeded = Scenario {seed: base.seed.wrapping_add(run as u64), ..scenario.clone()};
+      let result = run_scenario(&seeded, history.clone(), None);
+      let valuations = value_portfolios(&result.endowments, &result.history);
+      let trades = run_trades(&result.history);
+      println!("Configuration {}/{} run {}/{} (seed {}): {} trades", config + 1, scenarios.len(), run + 1, runs, seeded.seed, trades.len());
+
+      let (final_price, volatility) = price_metrics(&result.history, &trades);
+      writeln!(writer, "{},{},{},{},{},{},{},{},{},{},{},{:.4},{:.6},{}",
+               config + 1, seeded.seed, seeded.starting_money, seeded.corporate_starting_quantity, seeded.random_actors,
+               seeded.tick, seeded.fee_bps, trades.len(),
+               trades.iter().map(|trade| trade.quantity()).sum::<usize>(),
+               trades.iter().map(|trade| trade.price()).sum::<usize>(),
+               trades.iter().map(|trade| trade.fee).sum::<usize>(),
+               final_price, volatility, kind_returns(&valuations).join(","))?;
+      writer.flush()?;
+      rows += 1;
+    }
+  }
+  Ok(rows)
+}
+
+fn price_metrics(history: &MarketHistory, trades: &[&Trade]) -> (f64, f64) {
+  let mut final_prices = vec![];
+  let mut volatilities = vec![];
+  for stock_id in history.stocks.iter() {
+    let of_stock: Vec<&Trade> = trades.iter().filter(|trade| trade.buyer.stock_id == *stock_id).cloned().collect();
+    let prices = unit_prices(&of_stock);
+    match prices.last() {
+      Some(price) => {final_prices.push(*price);},
+      None => {continue;}
+    }
+    volatilities.push(volatility(&prices));
+  }
+  (mean(&final_prices), mean(&volatilities))
+}
+
+//Mean return of each of ACTOR_KINDS, empty for a type that wasn't in the run.
+fn kind_returns(valuations: &[Valuation]) -> Vec<String> {
+  ACTOR_KINDS.iter().map(|kind| {
+    let returns: Vec<f64> = valuations.iter().filter(|valuation| valuation.kind == *kind).map(|valuation| valuation.return_pct()).collect();
+    if returns.is_empty() {"".to_string()} else {format!("{:.4}", mean(&returns))}
+  }).collect()
+}
@@ -1,28 +1,32 @@
 use std::sync::mpsc::{Sender, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use messages::{TransactionRequest, MarketMessages, TellerMessages};
-use messages::MarketMessages::{MatchRequest};
-use messages::TellerMessages::{SellRequest, BuyRequest, RevokeRequest, RequestCount};
-use messages::ActorMessages::{ReceiveActivityCount};
+use crate::messages::{TransactionRequest, MarketMessages, TellerMessages, Side};
+use crate::messages::MarketMessages::{MatchRequest, Quote};
+use crate::messages::TellerMessages::{SellRequest, BuyRequest, RevokeRequest, RevokeAll, RequestCount, RequestQuote, Shutdown};
+use crate::messages::ActorMessages::{ReceiveActivityCount};
 
 struct Teller {
   buy_requests: Vec<TransactionRequest>,
   sell_requests: Vec<TransactionRequest>
 }
 
-pub fn start_teller(teller_id: usize, market_tx: Sender<MarketMessages>, teller_rx: Receiver<TellerMessages>) {
+//shutdown lets a teller with a long backlog stop without working through its whole queue first.
+pub fn start_teller(teller_id: usize, market_tx: Sender<MarketMessages>, teller_rx: Receiver<TellerMessages>, shutdown: Arc<AtomicBool>) {
   let mut teller = Teller{buy_requests: vec![],
                           sell_requests: vec![]};
 
-  loop {
-    let message = teller_rx.recv().unwrap();
+  //The market dropping its end of the channel also ends the teller.
+  while let Ok(message) = teller_rx.recv() {
+    let message = if shutdown.load(Ordering::SeqCst) {Shutdown} else {message};
     match message {
       RequestCount(actor_tx, buying) => {
         if buying {
-          actor_tx.send(ReceiveActivityCount(teller_id, true, teller.buy_requests.len())).unwrap();
+          let _ = actor_tx.send(ReceiveActivityCount(teller_id, true, teller.buy_requests.len()));
         }
         else {
-          actor_tx.send(ReceiveActivityCount(teller_id, false, teller.sell_requests.len())).unwrap();
+          let _ = actor_tx.send(ReceiveActivityCount(teller_id, false, teller.sell_requests.len()));
         }
       }
       BuyRequest(request) => {
@@ -30,24 +34,47 @@ pub fn start_teller(teller_id: usize, market_tx: Sender<MarketMessages>, teller_
           continue;
         }
         teller.buy_requests.push(request.clone());
-        match make_buy_request(&mut teller) {
-          Some(sell) => {market_tx.send(MatchRequest(request, sell)).unwrap();}
-          None => {} };},
-        //println!("RECEIVED BUY REQUEST")},
+        if let Some(sell) = make_buy_request(&mut teller) {
+          market_tx.send(MatchRequest(request, sell, Side::Buy)).unwrap();
+        }
+        //println!("RECEIVED BUY REQUEST")
+      },
       SellRequest(request) => {
         if request.quantity == 0 || request.price == 0 {
           continue;
         }
         teller.sell_requests.push(request.clone());
-        match make_sell_request(&mut teller) {
-          Some(buy) => {market_tx.send(MatchRequest(buy, request)).unwrap();}
-          None => {} };},
-        //println!("RECEIVED SELL REQUEST")},
-      RevokeRequest(actor_id, transaction_id) => {revoke(actor_id, transaction_id, &mut teller);}
+        if let Some(buy) = make_sell_request(&mut teller) {
+          market_tx.send(MatchRequest(buy, request, Side::Sell)).unwrap();
+        }
+        //println!("RECEIVED SELL REQUEST")
+      },
+      RevokeRequest(actor_id, transaction_id) => {revoke(actor_id, transaction_id, &mut teller);},
+      RevokeAll(actor_id) => {
+        teller.buy_requests.retain(|request| request.actor_id != actor_id);
+        teller.sell_requests.retain(|request| request.actor_id != actor_id);
+      },
+      RequestQuote(time) => {
+        //Per share, requests for different quantities can't be compared on their prices.
+        let best_bid = teller.buy_requests.iter().map(unit_price).reduce(f64::max);
+        let best_ask = teller.sell_requests.iter().map(unit_price).reduce(f64::min);
+        let _ = market_tx.send(Quote(teller_id, time, best_bid, best_ask));
+      },
+      Shutdown => {
+        let open_requests = teller.buy_requests.len() + teller.sell_requests.len();
+        teller.buy_requests.clear();
+        teller.sell_requests.clear();
+        println!("Teller {} shut down, cancelled {} open requests", teller_id, open_requests);
+        return;
+      }
     }
   }
 }
 
+fn unit_price(request: &TransactionRequest) -> f64 {
+  request.price as f64 / request.quantity as f64
+}
+
 fn make_buy_request(teller: &mut Teller) -> Option<TransactionRequest> {
   let new_buy = teller.buy_requests[teller.buy_requests.len() -1].clone();
   let mut matching_sell: Option<TransactionRequest> = None;
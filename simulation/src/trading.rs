@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use crate::messages::{ActorMessages, TransactionRequest};
+use crate::messages::MarketMessages::{BuyRequest, SellRequest, RevokeRequest};
+use crate::actor::Actor;
+
+/*
+Order handling shared by strategies that trade around the last price.
+
+A request's price covers its whole quantity and tellers match on those prices, the trade
+going through at the seller's price for as many shares as both sides have. Selling a share at
+a time gets the asked price per share from any bid at or above it. A one-share bid however only
+meets asks whose whole lot costs less than a share, so buying is done in one lot priced for all
+of its shares. That fills against bigger lots at or below the price per share, and can cost
+more per share when the seller has fewer shares than asked for.
+
+Tellers match a new order with the oldest one it crosses, so an order only gets to the front by
+resting. A strategy states each tick what it wants on a stock and the order already there is
+left alone if it is the same. A request that matches leaves the book on its own, so the order it
+belongs to loses that request's shares or quote level and the rest keep their places.
+
+A price that moves with every trade would re-price an order every tick and put it at the back of
+the queue each time. So a bid is left resting while it is still wanted and an offer is only
+re-priced when it has to go lower (entry_bid, buy_at_most and sell_at_most).
+
+Trend and mean reversion strategies enter and exit a position at a mark price of their own,
+paying slippage above it to buy and taking slippage below it to sell so their orders cross the
+book. entry_bid and exit_offer size and price those orders.
+
+A market maker rests on both sides at once, with one-share bids and asks at several prices.
+Those are kept the same way, as one quote per stock.
+*/
+
+#[derive(PartialEq)]
+enum Order {
+  Buy {price: usize, shares: usize}, //One request for all the shares
+  Sell {price: usize, shares: usize}, //One request per share
+  Quote {bids: Vec<usize>, asks: Vec<usize>} //One-share requests at each price
+}
+
+impl Order {
+  fn is_empty(&self) -> bool {
+    match self {
+      Order::Buy {price, shares} | Order::Sell {price, shares} => *price == 0 || *shares == 0,
+      Order::Quote {bids, asks} => bids.is_empty() && asks.is_empty()
+    }
+  }
+}
+
+struct Resting {
+  order: Order,
+  transaction_ids: Vec<usize>
+}
+
+#[derive(Default)]
+pub struct RestingOrders {
+  next_transaction_id: usize,
+  open: HashMap<usize, Resting> //stock_id, the orders resting for it
+}
+
+impl RestingOrders {
+  //Bids for shares in one request at price per share on every market.
+  pub fn buy(&mut self, actor: &Actor, stock_id: usize, price: usize, shares: usize) {
+    let order = Order::Buy {price, shares};
+    if self.replace(actor, stock_id, &order) {
+      let transaction_ids = vec![self.send(actor, true, stock_id, price * shares, shares)];
+      self.open.insert(stock_id, Resting {order, transaction_ids});
+    }
+  }
+
+  //Offers shares one at a time at price on every market.
+  pub fn sell(&mut self, actor: &Actor, stock_id: usize, price: usize, shares: usize) {
+    let order = Order::Sell {price, shares};
+    if self.replace(actor, stock_id, &order) {
+      let transaction_ids = (0..shares).map(|_| self.send(actor, false, stock_id, price, 1)).collect();
+      self.open.insert(stock_id, Resting {order, transaction_ids});
+    }
+  }
+
+  //Bids for and offers one share at each of the prices, both sides resting together.
+  pub fn quote(&mut self, actor: &Actor, stock_id: usize, bids: Vec<usize>, asks: Vec<usize>) {
+    let order = Order::Quote {bids, asks};
+    if self.replace(actor, stock_id, &order) {
+      let mut transaction_ids = vec![];
+      if let Order::Quote {bids, asks} = &order {
+        for price in bids.iter() {
+          transaction_ids.push(self.send(actor, true, stock_id, *price, 1));
+        }
+        for price in asks.iter() {
+          transaction_ids.push(self.send(actor, false, stock_id, *price, 1));
+        }
+      }
+      self.open.insert(stock_id, Resting {order, transaction_ids});
+    }
+  }
+
+  //Like buy, but keeps a bid already resting at or below price per share.
+  pub fn buy_at_most(&mut self, actor: &Actor, stock_id: usize, price: usize, shares: usize) {
+    match self.open.get(&stock_id).map(|resting| &resting.order) {
+      Some(Order::Buy {price: resting, ..}) if *resting <= price => {},
+      _ => {self.buy(actor, stock_id, price, shares);}
+    }
+  }
+
+  //Like sell, but keeps an offer for the same shares already resting at or below price.
+  pub fn sell_at_most(&mut self, actor: &Actor, stock_id: usize, price: usize, shares: usize) {
+    match self.open.get(&stock_id).map(|resting| &resting.order) {
+      Some(Order::Sell {price: resting, shares: resting_shares}) if *resting <= price && *resting_shares == shares => {},
+      _ => {self.sell(actor, stock_id, price, shares);}
+    }
+  }
+
+  //Bids position_fraction of cash or keeps the resting bid. Returns its cost, None for no whole share.
+  pub fn entry_bid(&mut self, actor: &Actor, stock_id: usize, mark: f64, cash: usize, position_fraction: f64, slippage: f64) -> Option<usize> {
+    if let Some(committed) = self.resting_bid(stock_id) {
+      return Some(committed);
+    }
+    let price = (mark * (1.0 + slippage)).ceil() as usize;
+    let budget = (cash as f64 * position_fraction) as usize;
+    let shares = budget.checked_div(price).unwrap_or(0);
+    if shares == 0 {
+      return None;
+    }
+    self.buy(actor, stock_id, price, shares);
+    Some(shares * price)
+  }
+
+  //Offers shares at slippage below mark through sell_at_most.
+  pub fn exit_offer(&mut self, actor: &Actor, stock_id: usize, mark: f64, shares: usize, slippage: f64) {
+    let price = ((mark * (1.0 - slippage)).floor() as usize).max(1);
+    self.sell_at_most(actor, stock_id, price, shares);
+  }
+
+  //What the bids resting for stock_id would cost together, None without any.
+  pub fn resting_bid(&self, stock_id: usize) -> Option<usize> {
+    match self.open.get(&stock_id).map(|resting| &resting.order) {
+      Some(Order::Buy {price, shares}) => Some(price * shares),
+      Some(Order::Quote {bids, ..}) if !bids.is_empty() => Some(bids.iter().sum()),
+      _ => None
+    }
+  }
+
+  //Takes back what is resting for stock_id.
+  pub fn revoke(&mut self, actor: &Actor, stock_id: usize) {
+    if let Some(resting) = self.open.remove(&stock_id) {
+      for transaction_id in resting.transaction_ids {
+        for (_, market_tx) in actor.markets.iter() {
+          let _ = market_tx.send(RevokeRequest(stock_id, actor.id, transaction_id));
+        }
+      }
+    }
+  }
+
+  //Takes back every order that has not matched yet.
+  pub fn revoke_all(&mut self, actor: &Actor) {
+    let stock_ids: Vec<usize> = self.open.keys().cloned().collect();
+    for stock_id in stock_ids {
+      self.revoke(actor, stock_id);
+    }
+  }
+
+  //Call with every message, a settlement being asked for means one of our requests matched and left the book.
+  pub fn record(&mut self, actor: &Actor, message: &ActorMessages) {
+    let transaction = match message {
+      ActorMessages::MoneyRequest(request) => &request.transaction,
+      ActorMessages::StockRequest(request) => &request.transaction,
+      _ => {return;}
+    };
+    let matched = if transaction.0.actor_id == actor.id {&transaction.0} else if transaction.1.actor_id == actor.id {&transaction.1} else {return;};
+    let resting = match self.open.get_mut(&matched.stock_id) {
+      Some(resting) => resting,
+      None => {return;}
+    };
+    let index = match resting.transaction_ids.iter().position(|id| *id == matched.transaction_id) {
+      Some(index) => index,
+      None => {return;}
+    };
+    resting.transaction_ids.remove(index);
+    match &mut resting.order {
+      Order::Buy {shares, ..} => {*shares = 0;},
+      Order::Sell {shares, ..} => {*shares -= 1;},
+      Order::Quote {bids, asks} => {
+        if index < bids.len() {bids.remove(index);} else {asks.remove(index - bids.len());}
+      }
+    }
+    if resting.order.is_empty() {
+      self.open.remove(&matched.stock_id);
+    }
+  }
+
+  //Whether a new order is needed, revoking the one resting for stock_id if it differs.
+  fn replace(&mut self, actor: &Actor, stock_id: usize, order: &Order) -> bool {
+    if order.is_empty() {
+      self.revoke(actor, stock_id);
+      return false;
+    }
+    match self.open.get(&stock_id) {
+      Some(resting) if resting.order == *order => false,
+      _ => {
+        self.revoke(actor, stock_id);
+        true
+      }
+    }
+  }
+
+  fn send(&mut self, actor: &Actor, buying: bool, stock_id: usize, price: usize, quantity: usize) -> usize {
+    let transaction = TransactionRequest {transaction_id: self.next_transaction_id, actor_id: actor.id, stock_id, price, quantity};
+    for (_, market_tx) in actor.markets.iter() {
+      let _ = market_tx.send(if buying {BuyRequest(transaction.clone())} else {SellRequest(transaction.clone())});
+    }
+    self.next_transaction_id += 1;
+    transaction.transaction_id
+  }
+}
+
+//What the shares an actor holds cost it, kept from the settlements it takes part in.
+#[derive(Default)]
+pub struct CostBasis {
+  positions: HashMap<usize, (usize, f64)> //stock_id, (shares, total cost)
+}
+
+impl CostBasis {
+  //Call with every message before actor::answer_settlement, which is what tells a buy from a sale.
+  pub fn record(&mut self, actor: &Actor, message: &ActorMessages) {
+    if let ActorMessages::CommitTransaction(counterpart) = message {
+      let position = self.positions.entry(counterpart.stock_id).or_insert((0, 0.0));
+      if actor.pending_money > 0 {
+        position.0 += counterpart.quantity;
+        position.1 += counterpart.price as f64;
+      }
+      if actor.pending_stock.1 > 0 && position.0 > 0 {
+        let sold = counterpart.quantity.min(position.0);
+        position.1 -= position.1 * sold as f64 / position.0 as f64;
+        position.0 -= sold;
+      }
+    }
+  }
+
+  //Average price paid per share still held.
+  pub fn entry_price(&self, stock_id: usize) -> Option<f64> {
+    match self.positions.get(&stock_id) {
+      Some((shares, cost)) if *shares > 0 => Some(cost / *shares as f64),
+      _ => None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::mpsc::{channel, Receiver};
+  use crate::messages::{MarketMessages, MoneyRequest, SharedHistory, StockRequest};
+
+  fn test_actor() -> (Actor, Receiver<MarketMessages>) {
+    let (market_tx, market_rx) = channel();
+    let actor = Actor {id: 1, money: 0, stocks: HashMap::new(), pending_money: 0, pending_stock: (0, 0),
+                       markets: HashMap::from([(0, market_tx)]), history: SharedHistory::default()};
+    (actor, market_rx)
+  }
+
+  //The ids of the requests sent since the last call, and how many revokes.
+  fn sent(market_rx: &Receiver<MarketMessages>) -> (Vec<usize>, usize) {
+    let mut ids = vec![];
+    let mut revokes = 0;
+    for message in market_rx.try_iter() {
+      match message {
+        BuyRequest(request) | SellRequest(request) => {ids.push(request.transaction_id);},
+        RevokeRequest(..) => {revokes += 1;},
+        _ => {}
+      }
+    }
+    (ids, revokes)
+  }
+
+  fn sold(actor: &Actor, transaction_id: usize, price: usize) -> ActorMessages {
+    let buyer = TransactionRequest {transaction_id: 0, actor_id: 2, stock_id: 0, price, quantity: 1};
+    let seller = TransactionRequest {transaction_id, actor_id: actor.id, stock_id: 0, price, quantity: 1};
+    ActorMessages::StockRequest(StockRequest {market_id: 0, stock_id: 0, quantity: 1, transaction: (buyer, seller)})
+  }
+
+  #[test]
+  fn a_fill_only_takes_the_matched_share_off_an_offer() {
+    let (actor, market_rx) = test_actor();
+    let mut orders = RestingOrders::default();
+    orders.sell(&actor, 0, 10, 3);
+    let (ids, _) = sent(&market_rx);
+    assert_eq!(ids.len(), 3);
+
+    orders.record(&actor, &sold(&actor, ids[1], 10));
+    assert_eq!(sent(&market_rx), (vec![], 0));
+    //The two shares left are still resting, so offering them again sends nothing.
+    orders.sell(&actor, 0, 10, 2);
+    assert_eq!(sent(&market_rx), (vec![], 0));
+    orders.revoke(&actor, 0);
+    assert_eq!(sent(&market_rx), (vec![], 2));
+  }
+
+  #[test]
+  fn a_fill_only_takes_the_matched_level_off_a_quote() {
+    let (actor, market_rx) = test_actor();
+    let mut orders = RestingOrders::default();
+    orders.quote(&actor, 0, vec![9, 8], vec![11, 12]);
+    let (ids, _) = sent(&market_rx);
+    assert_eq!(ids.len(), 4);
+
+    let buyer = TransactionRequest {transaction_id: ids[1], actor_id: actor.id, stock_id: 0, price: 8, quantity: 1};
+    let seller = TransactionRequest {transaction_id: 0, actor_id: 2, stock_id: 0, price: 8, quantity: 1};
+    orders.record(&actor, &ActorMessages::MoneyRequest(MoneyRequest {market_id: 0, amount: 8, transaction: (buyer, seller)}));
+    assert_eq!(sent(&market_rx), (vec![], 0));
+    assert_eq!(orders.resting_bid(0), Some(9));
+    orders.quote(&actor, 0, vec![9], vec![11, 12]);
+    assert_eq!(sent(&market_rx), (vec![], 0));
+  }
+}
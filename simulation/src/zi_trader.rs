@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+
+use crate::messages::{ActorMessages, MarketHistory, MarketMessages, SharedHistory};
+use crate::messages::ActorMessages::{History, Time, Stop};
+use crate::messages::MarketMessages::RegisterActor;
+use crate::actor::Actor;
+use crate::actor::{answer_settlement, has_pending_transaction, status};
+use crate::stats::mean;
+use crate::portfolio::run_trades;
+use crate::trading::RestingOrders;
+
+/*
+Zero intelligence traders under a budget constraint (ZI-C, Gode and Sunder 1993).
+Every buyer has a private redemption value for each unit it may buy and every seller a cost
+for each unit it may sell, drawn uniformly from the demand and supply ranges. Buyers buy their
+most valuable unit first and sellers sell their cheapest first. Each tick a trader replaces its
+order for the next unit with one at a random price: a buyer bids between 1 and the unit's value,
+a seller asks between the unit's cost and max_price. So no trader ever trades at a loss, and
+that alone should get the market close to its competitive equilibrium.
+
+The surplus of a trade is the buyer's value less the price plus the price less the seller's
+cost, and the most a market can realize is that of the competitive allocation, where every
+unit worth more to a buyer than it costs a seller is traded. Efficiency is one over the other.
+Trades go through at the seller's price. Other actors trading the same stock would take units
+from the ZI traders without a value of their own, so efficiency is best measured in a run with
+only ZI traders.
+*/
+
+#[derive(Clone)]
+pub struct ZiConfig {
+  pub buyers: usize,
+  pub sellers: usize,
+  pub units: usize, //Units each trader may trade
+  pub value_range: (usize, usize), //Demand, the lowest and highest redemption value
+  pub cost_range: (usize, usize), //Supply, the lowest and highest cost
+  pub max_price: usize, //Highest ask a seller makes
+  pub stock_id: usize
+}
+
+impl Default for ZiConfig {
+  fn default() -> ZiConfig {
+    ZiConfig {buyers: 0, sellers: 0, units: 3, value_range: (20, 80), cost_range: (10, 70), max_price: 100, stock_id: 0}
+  }
+}
+
+//A trader's private schedule, units in the order it trades them.
+#[derive(Clone)]
+pub struct ZiTrader {
+  pub actor_id: usize,
+  pub buyer: bool,
+  pub units: Vec<usize> //Values from the highest down for a buyer, costs from the lowest up for a seller
+}
+
+impl ZiTrader {
+  pub fn kind(&self) -> &'static str {
+    if self.buyer {"ZI-C Buyer"} else {"ZI-C Seller"}
+  }
+}
+
+//Draws everyone's values and costs, buyers first, actor ids counting up from first_id.
+pub fn draw_traders(config: &ZiConfig, seed: u64, first_id: usize) -> Vec<ZiTrader> {
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut traders = vec![];
+  for i in 0..config.buyers + config.sellers {
+    let buyer = i < config.buyers;
+    let (low, high) = if buyer {config.value_range} else {config.cost_range};
+    let mut units: Vec<usize> = (0..config.units).map(|_| rng.gen_range(low.min(high)..=high.max(low))).collect();
+    units.sort();
+    if buyer {
+      units.reverse();
+    }
+    traders.push(ZiTrader {actor_id: first_id + i, buyer, units});
+  }
+  traders
+}
+
+pub fn start_zi_trader(trader: ZiTrader, existing_markets: HashMap<usize, Sender<MarketMessages>>, starting_money: usize, config: ZiConfig, seed: u64, actor_tx: Sender<ActorMessages>, actor_rx: Receiver<ActorMessages>) {
+  println!("Starting {} {}", trader.kind(), trader.actor_id);
+  let mut actor = Actor { id: trader.actor_id,
+                          money: starting_money,
+                          stocks: HashMap::new(),
+                          pending_money: 0,
+                          pending_stock: (0, 0),
+                          markets: existing_markets,
+                          history: SharedHistory::default()};
+  if !trader.buyer {
+    actor.stocks.insert(config.stock_id, trader.units.len());
+  }
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut orders = RestingOrders::default();
+
+  for (_, market_tx) in actor.markets.iter() {
+    market_tx.send(RegisterActor(actor.id, actor_tx.clone())).unwrap();
+  }
+
+  loop {
+    match actor_rx.try_recv() {
+      Ok(message) => {
+        orders.record(&actor, &message);
+        match answer_settlement(&mut actor, message) {
+          Some(History(history)) => {actor.history = history;},
+          Some(Time(_, _)) => {quote(&actor, &trader, &config, &mut rng, &mut orders);},
+          Some(Stop(main_channel)) => {
+            main_channel.send((actor.id, format!("({}) {}", trader.kind(), status(&actor)))).unwrap();
+            return;
+          },
+          _ => {}
+        }
+      },
+      Err(TryRecvError::Empty) => {thread::sleep(Duration::from_millis(1));},
+      Err(TryRecvError::Disconnected) => {println!("ERROR: {} {} disconnected", trader.kind(), actor.id); return;}
+    }
+  }
+}
+
+fn quote(actor: &Actor, trader: &ZiTrader, config: &ZiConfig, rng: &mut StdRng, orders: &mut RestingOrders) {
+  if has_pending_transaction(actor) {
+    return;
+  }
+  let held = *actor.stocks.get(&config.stock_id).unwrap_or(&0);
+  //Units bought so far for a buyer, sold so far for a seller.
+  let traded = if trader.buyer {held} else {trader.units.len().saturating_sub(held)};
+  match trader.units.get(traded) {
+    Some(unit) if trader.buyer => {
+      let limit = (*unit).min(actor.money);
+      if limit > 0 {
+        orders.buy(actor, config.stock_id, rng.gen_range(1..=limit), 1);
+      }
+      else {
+        orders.revoke(actor, config.stock_id);
+      }
+    },
+    Some(unit) if held > 0 => {
+      let floor = (*unit).max(1);
+      orders.sell(actor, config.stock_id, rng.gen_range(floor..=config.max_price.max(floor)), 1);
+    },
+    _ => {orders.revoke(actor, config.stock_id);}
+  }
+}
+
+pub struct Efficiency {
+  pub realized: f64,
+  pub maximum: usize,
+  pub traded: usize, //Units with a ZI trader on at least one side
+  pub mean_price: Option<f64>,
+  pub equilibrium_quantity: usize,
+  pub equilibrium_prices: Option<(usize, usize)> //Every price in the range clears the market
+}
+
+impl Efficiency {
+  //Realized over maximum surplus, in percent.
+  pub fn efficiency(&self) -> Option<f64> {
+    if self.maximum == 0 {None} else {Some(100.0 * self.realized / self.maximum as f64)}
+  }
+}
+
+//The competitive equilibrium of the schedules and the surplus the run's trades realized.
+pub fn efficiency(traders: &[ZiTrader], history: &MarketHistory, stock_id: usize) -> Efficiency {
+  let mut values: Vec<usize> = traders.iter().filter(|t| t.buyer).flat_map(|t| t.units.iter().cloned()).collect();
+  let mut costs: Vec<usize> = traders.iter().filter(|t| !t.buyer).flat_map(|t| t.units.iter().cloned()).collect();
+  values.sort_by(|a, b| b.cmp(a));
+  costs.sort();
+  let quantity = values.iter().zip(costs.iter()).take_while(|(value, cost)| value >= cost).count();
+  let maximum = values.iter().zip(costs.iter()).take(quantity).map(|(value, cost)| value - cost).sum();
+  let equilibrium_prices = if quantity == 0 {None} else {
+    let low = costs[quantity - 1].max(values.get(quantity).cloned().unwrap_or(0));
+    let high = values[quantity - 1].min(costs.get(quantity).cloned().unwrap_or(usize::MAX));
+    Some((low, high))
+  };
+
+  let by_id: HashMap<usize, &ZiTrader> = traders.iter().map(|trader| (trader.actor_id, trader)).collect();
+  let mut traded_units: HashMap<usize, usize> = HashMap::new();
+  let mut realized = 0.0;
+  let mut traded = 0;
+  let mut prices = vec![];
+  for trade in run_trades(history).into_iter().filter(|trade| trade.buyer.stock_id == stock_id) {
+    let price = match trade.unit_price() {
+      Some(price) => price,
+      None => {continue;}
+    };
+    let mut involved = false;
+    for actor_id in [trade.buyer.actor_id, trade.seller.actor_id] {
+      let trader = match by_id.get(&actor_id) {
+        Some(trader) => trader,
+        None => {continue;}
+      };
+      involved = true;
+      for _ in 0..trade.quantity() {
+        let count = traded_units.entry(actor_id).or_insert(0);
+        if let Some(unit) = trader.units.get(*count) {
+          realized += if trader.buyer {*unit as f64 - price} else {price - *unit as f64};
+        }
+        *count += 1;
+      }
+    }
+    if involved {
+      traded += trade.quantity();
+      prices.push(price);
+    }
+  }
+
+  Efficiency {realized,
+              maximum,
+              traded,
+              mean_price: if prices.is_empty() {None} else {Some(mean(&prices))},
+              equilibrium_quantity: quantity,
+              equilibrium_prices}
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::messages::{Side, TransactionRequest};
+
+  //Demand 50 45 40 30 20 against supply 10 25 35 42 60: three units cross, for 40 + 20 + 5.
+  fn schedules() -> Vec<ZiTrader> {
+    vec![ZiTrader {actor_id: 0, buyer: true, units: vec![50, 40, 30]},
+         ZiTrader {actor_id: 1, buyer: true, units: vec![45, 20]},
+         ZiTrader {actor_id: 2, buyer: false, units: vec![10, 25, 35]},
+         ZiTrader {actor_id: 3, buyer: false, units: vec![42, 60]}]
+  }
+
+  fn record(history: &mut MarketHistory, buyer: usize, seller: usize, stock_id: usize, price: usize) {
+    let request = |actor_id| TransactionRequest {transaction_id: 0, actor_id, stock_id, price, quantity: 1};
+    history.record_trade(0, history.next_seq, (request(buyer), request(seller)), Side::Buy, 0, 0);
+  }
+
+  #[test]
+  fn competitive_equilibrium() {
+    let result = efficiency(&schedules(), &MarketHistory::default(), 0);
+    assert_eq!(result.equilibrium_quantity, 3);
+    assert_eq!(result.maximum, 65);
+    //The last traded pair bounds the price from inside, the first untraded pair from outside.
+    assert_eq!(result.equilibrium_prices, Some((35, 40)));
+    assert_eq!(result.traded, 0);
+    assert_eq!(result.mean_price, None);
+    assert_eq!(result.efficiency(), Some(0.0));
+  }
+
+  #[test]
+  fn no_equilibrium_when_nothing_crosses() {
+    let traders = vec![ZiTrader {actor_id: 0, buyer: true, units: vec![20, 10]},
+                       ZiTrader {actor_id: 1, buyer: false, units: vec![30, 40]}];
+    let result = efficiency(&traders, &MarketHistory::default(), 0);
+    assert_eq!(result.equilibrium_quantity, 0);
+    assert_eq!(result.maximum, 0);
+    assert_eq!(result.equilibrium_prices, None);
+    assert_eq!(result.efficiency(), None);
+  }
+
+  #[test]
+  fn realized_surplus_follows_the_schedules() {
+    let mut history = MarketHistory::default();
+    record(&mut history, 0, 2, 0, 30); //(50 - 30) + (30 - 10)
+    record(&mut history, 1, 2, 0, 30); //(45 - 30) + (30 - 25)
+    record(&mut history, 0, 2, 1, 30); //Another stock
+    record(&mut history, 7, 8, 0, 90); //No ZI trader involved
+    let result = efficiency(&schedules(), &history, 0);
+    assert_eq!(result.traded, 2);
+    assert_eq!(result.mean_price, Some(30.0));
+    assert!((result.realized - 60.0).abs() < 1e-9);
+    assert!((result.efficiency().unwrap() - 6000.0 / 65.0).abs() < 1e-9);
+  }
+}